@@ -0,0 +1,14 @@
+use std::env;
+
+fn main() {
+    // libbpf-sys installs libbpf's headers when it builds its vendored copy,
+    // and only passes their directory to the build scripts of crates that
+    // depend on it directly, so bake it into bpf-build for its callers.
+    println!("cargo:rerun-if-env-changed=DEP_BPF_INCLUDE");
+    if let Some(dir) = env::var_os("DEP_BPF_INCLUDE") {
+        println!(
+            "cargo:rustc-env=BPF_BUILD_LIBBPF_INCLUDE={}",
+            dir.to_string_lossy()
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! Compiling BPF C sources from a Cargo build script, so the object can be
+//! embedded in the binary instead of being built by a Makefile and found at
+//! run time.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() -> Result<(), bpf_build::Error> {
+//!     bpf_build::BpfBuilder::new("bpf/src/filter.c").compile()?;
+//!     Ok(())
+//! }
+//!
+//! // main.rs
+//! const FILTER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/filter.o"));
+//!
+//! let mut obj = bpf::Object::create(FILTER)?;
+//! ```
+//!
+//! Sources are compiled with clang for the BPF target of the crate's
+//! endianness, with `-g` so the object has BTF, and with `__TARGET_ARCH_*`
+//! defined for `bpf_tracing.h`. Clang is found on the `PATH`, or in the
+//! `CLANG` environment variable.
+//!
+//! CO-RE programs include `vmlinux.h`. For a hermetic build, check one in and
+//! pass it to [BpfBuilder::vmlinux]; [BpfBuilder::vmlinux_from_kernel] dumps
+//! one from the BTF of the running kernel instead, with bpftool.
+//!
+//! libbpf's headers, such as `<bpf/bpf_helpers.h>`, come from the copy of
+//! libbpf that libbpf-sys builds. Where libbpf-sys doesn't build one, as under
+//! Bazel, they come from the host's libbpf-dev instead. UAPI headers such as
+//! `<linux/bpf.h>` always come from the host's kernel headers, with
+//! `<asm/types.h>` under `/usr/include/<arch>-linux-gnu`; CO-RE sources that
+//! include only `vmlinux.h` and libbpf's headers don't need them.
+//!
+//! Paths are relative to the crate, so the same build script works under
+//! Cargo and under Bazel's `cargo_build_script`, as long as the sources are
+//! in its `data`.
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0} is not set, bpf-build only runs in a build script")]
+    Env(&'static str),
+    #[error("failed to run \"{program}\": {source}")]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("\"{program}\" failed with {status}:\n{stderr}")]
+    Failed {
+        program: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    #[error("failed to write \"{}\": {source}", .path.display())]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Where `vmlinux.h` comes from, if the source includes it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Vmlinux {
+    None,
+    Header(PathBuf),
+    Kernel,
+}
+
+/// Compiles one BPF C source into an object in `OUT_DIR`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BpfBuilder {
+    source: PathBuf,
+    object: Option<String>,
+    clang: Option<PathBuf>,
+    includes: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    flags: Vec<String>,
+    vmlinux: Vmlinux,
+}
+
+impl BpfBuilder {
+    /// `source` is relative to the crate being built.
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(source: P) -> Self {
+        BpfBuilder {
+            source: source.as_ref().to_owned(),
+            object: None,
+            clang: None,
+            includes: vec![],
+            defines: vec![],
+            flags: vec![],
+            vmlinux: Vmlinux::None,
+        }
+    }
+
+    /// File name of the object in `OUT_DIR`, by default the name of the
+    /// source with a `.o` extension.
+    #[must_use]
+    pub fn object<S: Into<String>>(mut self, name: S) -> Self {
+        self.object = Some(name.into());
+        self
+    }
+
+    /// Use the clang at `path`, rather than `$CLANG` or the one on the
+    /// `PATH`.
+    #[must_use]
+    pub fn clang<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.clang = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Add an include directory, relative to the crate.
+    #[must_use]
+    pub fn include<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.includes.push(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Define a macro, as `-D name` or `-D name=value`.
+    #[must_use]
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.to_owned(), value.map(ToOwned::to_owned)));
+        self
+    }
+
+    /// Pass an extra flag to clang.
+    #[must_use]
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_owned());
+        self
+    }
+
+    /// Make a checked in `vmlinux.h` includable, which keeps the build
+    /// independent of the kernel it runs on.
+    #[must_use]
+    pub fn vmlinux<P: AsRef<Path>>(mut self, header: P) -> Self {
+        self.vmlinux = Vmlinux::Header(header.as_ref().to_owned());
+        self
+    }
+
+    /// Generate `vmlinux.h` from `/sys/kernel/btf/vmlinux` with bpftool,
+    /// which ties the build to the kernel it runs on.
+    #[must_use]
+    pub fn vmlinux_from_kernel(mut self) -> Self {
+        self.vmlinux = Vmlinux::Kernel;
+        self
+    }
+
+    /// Compile the source, returning the path of the object. Warnings are
+    /// passed on to Cargo.
+    pub fn compile(&self) -> Result<PathBuf> {
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").ok_or(Error::Env("OUT_DIR"))?);
+        let manifest_dir = PathBuf::from(
+            env::var_os("CARGO_MANIFEST_DIR").ok_or(Error::Env("CARGO_MANIFEST_DIR"))?,
+        );
+
+        let vmlinux_dir = match &self.vmlinux {
+            Vmlinux::None => None,
+            Vmlinux::Header(header) => {
+                let header = manifest_dir.join(header);
+                println!("cargo:rerun-if-changed={}", header.display());
+                header.parent().map(Path::to_owned)
+            }
+            Vmlinux::Kernel => Some(dump_vmlinux(&out_dir)?),
+        };
+
+        let source = manifest_dir.join(&self.source);
+        let object = out_dir.join(self.object_name());
+        let mut cmd = self.command(&manifest_dir, &source, &object);
+        if let Some(dir) = vmlinux_dir {
+            cmd.arg("-I").arg(dir);
+        }
+
+        println!("cargo:rerun-if-changed={}", source.display());
+        for dir in &self.includes {
+            println!(
+                "cargo:rerun-if-changed={}",
+                manifest_dir.join(dir).display()
+            );
+        }
+        println!("cargo:rerun-if-env-changed=CLANG");
+
+        let stderr = run(&mut cmd)?;
+        for line in stderr.lines() {
+            println!("cargo:warning={line}");
+        }
+        Ok(object)
+    }
+
+    #[must_use]
+    fn object_name(&self) -> String {
+        match &self.object {
+            Some(name) => name.clone(),
+            None => {
+                let stem = self.source.file_stem().unwrap_or_default();
+                format!("{}.o", stem.to_string_lossy())
+            }
+        }
+    }
+
+    /// The clang invocation for everything but `vmlinux.h`.
+    #[must_use]
+    fn command(&self, manifest_dir: &Path, source: &Path, object: &Path) -> Command {
+        let clang = match (&self.clang, env::var_os("CLANG")) {
+            (Some(clang), _) => clang.clone(),
+            (None, Some(clang)) => clang.into(),
+            (None, None) => "clang".into(),
+        };
+        let target_arch =
+            env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_else(|_| env::consts::ARCH.to_owned());
+        let target = match env::var("CARGO_CFG_TARGET_ENDIAN").as_deref() {
+            Ok("big") => "bpfeb",
+            _ => "bpfel",
+        };
+
+        let mut cmd = Command::new(clang);
+        cmd.args(["-g", "-O2", "-Wall", "-target", target]);
+        cmd.arg(format!("-D__TARGET_ARCH_{}", kernel_arch(&target_arch)));
+        for (name, value) in &self.defines {
+            match value {
+                Some(value) => cmd.arg(format!("-D{name}={value}")),
+                None => cmd.arg(format!("-D{name}")),
+            };
+        }
+        for dir in &self.includes {
+            cmd.arg("-I").arg(manifest_dir.join(dir));
+        }
+        if let Some(dir) = option_env!("BPF_BUILD_LIBBPF_INCLUDE") {
+            cmd.arg("-I").arg(dir);
+        }
+        // The BPF target has no system include directory of its own, so
+        // <linux/bpf.h> needs the host's for <asm/types.h>.
+        let multiarch = Path::new("/usr/include").join(format!("{target_arch}-linux-gnu"));
+        if multiarch.is_dir() {
+            cmd.arg("-idirafter").arg(multiarch);
+        }
+        cmd.args(&self.flags);
+        cmd.arg("-c").arg(source).arg("-o").arg(object);
+        cmd
+    }
+}
+
+/// The architecture name the kernel and `bpf_tracing.h` use for a Rust
+/// target architecture.
+#[must_use]
+fn kernel_arch(target_arch: &str) -> &str {
+    match target_arch {
+        "x86_64" | "x86" => "x86",
+        "aarch64" => "arm64",
+        "riscv64" => "riscv",
+        "powerpc64" | "powerpc" => "powerpc",
+        "s390x" => "s390",
+        "loongarch64" => "loongarch",
+        "mips" | "mips64" => "mips",
+        arch => arch,
+    }
+}
+
+/// Write the running kernel's `vmlinux.h` into a directory of `out_dir`,
+/// returning the directory.
+fn dump_vmlinux(out_dir: &Path) -> Result<PathBuf> {
+    let mut cmd = Command::new("bpftool");
+    cmd.args([
+        "btf",
+        "dump",
+        "file",
+        "/sys/kernel/btf/vmlinux",
+        "format",
+        "c",
+    ]);
+    let output = cmd.output().map_err(|source| Error::Spawn {
+        program: "bpftool".into(),
+        source,
+    })?;
+    if !output.status.success() {
+        return Err(Error::Failed {
+            program: "bpftool".into(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    let dir = out_dir.join("vmlinux");
+    let header = dir.join("vmlinux.h");
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&header, output.stdout))
+        .map_err(|source| Error::Write {
+            path: header,
+            source,
+        })?;
+    Ok(dir)
+}
+
+/// Run `cmd`, returning its stderr.
+fn run(cmd: &mut Command) -> Result<String> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let output = cmd.output().map_err(|source| Error::Spawn {
+        program: program.clone(),
+        source,
+    })?;
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    match output.status.success() {
+        true => Ok(stderr),
+        false => Err(Error::Failed {
+            program,
+            status: output.status,
+            stderr,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_name_defaults_to_source_stem() {
+        assert_eq!("bpf.o", BpfBuilder::new("bpf/src/bpf.c").object_name());
+        assert_eq!(
+            "filter.o",
+            BpfBuilder::new("bpf.c").object("filter.o").object_name()
+        );
+    }
+
+    #[test]
+    fn test_command_targets_bpf() {
+        let cmd = BpfBuilder::new("bpf/src/bpf.c")
+            .clang("/usr/bin/clang-17")
+            .include("bpf/include")
+            .define("MAX_QUEUES", Some("64"))
+            .command(
+                Path::new("/src/crate"),
+                Path::new("/src/crate/bpf/src/bpf.c"),
+                Path::new("/out/bpf.o"),
+            );
+        let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+
+        assert_eq!("/usr/bin/clang-17", cmd.get_program());
+        assert!(args.windows(2).any(|arg| arg == ["-target", "bpfel"]));
+        assert!(args.contains(&"-g"));
+        assert!(args.contains(&"-DMAX_QUEUES=64"));
+        assert!(args
+            .windows(2)
+            .any(|arg| arg == ["-I", "/src/crate/bpf/include"]));
+        assert!(args.ends_with(&["-c", "/src/crate/bpf/src/bpf.c", "-o", "/out/bpf.o"]));
+    }
+
+    #[test]
+    fn test_kernel_arch() {
+        assert_eq!("x86", kernel_arch("x86_64"));
+        assert_eq!("arm64", kernel_arch("aarch64"));
+        assert_eq!("sparc", kernel_arch("sparc"));
+    }
+}
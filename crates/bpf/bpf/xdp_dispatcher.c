@@ -0,0 +1,81 @@
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+/**
+ * The dispatcher follows libxdp's protocol, so that `xdp-loader` and
+ * `XdpDispatcher` can add programs to each other's dispatchers. The layout of
+ * the config, the names of the slots and the metadata below must not change.
+ */
+#define XDP_METADATA_SECTION "xdp_metadata"
+#define XDP_DISPATCHER_VERSION 2
+#define XDP_DISPATCHER_MAGIC 236
+#define XDP_DISPATCHER_RETVAL 31
+#define MAX_DISPATCHER_ACTIONS 10
+
+struct xdp_dispatcher_config {
+  __u8 magic;
+  __u8 dispatcher_version;
+  __u8 num_progs_enabled;
+  __u8 is_xdp_frags;
+  __u32 chain_call_actions[MAX_DISPATCHER_ACTIONS];
+  __u32 run_prios[MAX_DISPATCHER_ACTIONS];
+  __u32 program_flags[MAX_DISPATCHER_ACTIONS];
+};
+
+/* Set by `XdpDispatcher` before the dispatcher is loaded. */
+static volatile const struct xdp_dispatcher_config conf = {};
+
+/**
+ * A slot that a program replaces with freplace. Slots must be global
+ * functions to be replaced. An empty slot returns XDP_DISPATCHER_RETVAL,
+ * which is always a chain call action so the next slot runs.
+ */
+#define SLOT(n)                                  \
+  __attribute__((noinline)) int prog##n(struct xdp_md* ctx) { \
+    volatile int ret = XDP_DISPATCHER_RETVAL;    \
+                                                 \
+    if (!ctx) return XDP_ABORTED;                \
+    return ret;                                  \
+  }
+
+SLOT(0)
+SLOT(1)
+SLOT(2)
+SLOT(3)
+SLOT(4)
+SLOT(5)
+SLOT(6)
+SLOT(7)
+SLOT(8)
+SLOT(9)
+
+/**
+ * Run the enabled slots in order until one returns an action that is not one
+ * of its chain call actions, which is the result of the dispatcher.
+ */
+#define RUN(n)                                        \
+  if (num_progs_enabled < n + 1) goto out;            \
+  ret = prog##n(ctx);                                 \
+  if (!((1U << ret) & conf.chain_call_actions[n])) return ret;
+
+SEC("xdp")
+int xdp_dispatcher(struct xdp_md* ctx) {
+  __u8 num_progs_enabled = conf.num_progs_enabled;
+  int ret;
+
+  RUN(0)
+  RUN(1)
+  RUN(2)
+  RUN(3)
+  RUN(4)
+  RUN(5)
+  RUN(6)
+  RUN(7)
+  RUN(8)
+  RUN(9)
+out:
+  return XDP_PASS;
+}
+
+char _license[] SEC("license") = "GPL";
+__uint(dispatcher_version, XDP_DISPATCHER_VERSION) SEC(XDP_METADATA_SECTION);
@@ -0,0 +1,338 @@
+//! Consumers for the samples BPF programs send to userspace through a ring
+//! buffer (`BPF_MAP_TYPE_RINGBUF`) or a perf buffer
+//! (`BPF_MAP_TYPE_PERF_EVENT_ARRAY`). Samples are passed to callbacks on the
+//! thread that calls `poll` or `consume`.
+//!
+//! ```ignore
+//! let mut drops = HashMap::new();
+//! let mut events = RingBufConsumer::new(&obj.find_map("drops")?, |sample| {
+//!     *drops.entry(sample[0]).or_insert(0) += 1;
+//! })?;
+//! while running() {
+//!     events.poll(Some(Duration::from_millis(100)))?;
+//! }
+//! ```
+//!
+//! Both consumers expose an epoll fd that becomes readable when samples are
+//! ready, so an async runtime can wait on it and call `consume`. A callback
+//! that panics aborts the process, since it is called from libbpf.
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::ptr::{null, NonNull};
+use std::time::Duration;
+
+use crate::{dup_fd, Errno, Error, Map, Result};
+
+type RingBufCallback<'cb> = Box<dyn FnMut(&[u8]) + 'cb>;
+type PerfSampleCallback<'cb> = Box<dyn FnMut(i32, &[u8]) + 'cb>;
+type PerfLostCallback<'cb> = Box<dyn FnMut(i32, u64) + 'cb>;
+
+/// Consumer of one or more ring buffers, each with its own callback. Unlike
+/// a perf buffer, a ring buffer never drops samples on the consumer side: a
+/// program whose `bpf_ringbuf_reserve` fails has to count that itself.
+pub struct RingBufConsumer<'cb> {
+    rb: NonNull<libbpf_sys::ring_buffer>,
+    // Boxed again so each callback has a thin pointer to pass to libbpf,
+    // which stays put when the vector grows.
+    #[allow(clippy::vec_box)]
+    callbacks: Vec<Box<RingBufCallback<'cb>>>,
+}
+
+impl<'cb> RingBufConsumer<'cb> {
+    /// Consume samples from a loaded ring buffer map.
+    pub fn new<F: FnMut(&[u8]) + 'cb>(map: &Map<'_>, callback: F) -> Result<Self> {
+        check_map_type(map, libbpf_sys::BPF_MAP_TYPE_RINGBUF, "ringbuf")?;
+        let mut callback: Box<RingBufCallback<'cb>> = Box::new(Box::new(callback));
+        let rb = unsafe {
+            libbpf_sys::ring_buffer__new(
+                map.fd(),
+                Some(ring_sample),
+                &mut *callback as *mut RingBufCallback as _,
+                null(),
+            )
+        };
+        match NonNull::new(rb) {
+            Some(rb) => Ok(RingBufConsumer {
+                rb,
+                callbacks: vec![callback],
+            }),
+            None => Err(Error::Buffer {
+                errno: Errno::last(),
+                map: map.name(),
+            }),
+        }
+    }
+
+    /// Also consume samples from another ring buffer map, with its own
+    /// callback.
+    pub fn add<F: FnMut(&[u8]) + 'cb>(&mut self, map: &Map<'_>, callback: F) -> Result<()> {
+        check_map_type(map, libbpf_sys::BPF_MAP_TYPE_RINGBUF, "ringbuf")?;
+        let mut callback: Box<RingBufCallback<'cb>> = Box::new(Box::new(callback));
+        match unsafe {
+            libbpf_sys::ring_buffer__add(
+                self.rb.as_ptr(),
+                map.fd(),
+                Some(ring_sample),
+                &mut *callback as *mut RingBufCallback as _,
+            )
+        } {
+            ret if ret < 0 => Err(Error::Buffer {
+                errno: Errno::from_ret(ret),
+                map: map.name(),
+            }),
+            _ => {
+                self.callbacks.push(callback);
+                Ok(())
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for samples, or until there are some if it is
+    /// `None`, and pass them to the callbacks. Returns how many samples were
+    /// consumed, which is zero if the wait was interrupted by a signal.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<usize> {
+        poll_result(unsafe { libbpf_sys::ring_buffer__poll(self.rb.as_ptr(), timeout_ms(timeout)) })
+    }
+
+    /// Pass the samples that are ready to the callbacks without waiting.
+    pub fn consume(&mut self) -> Result<usize> {
+        poll_result(unsafe { libbpf_sys::ring_buffer__consume(self.rb.as_ptr()) })
+    }
+
+    /// An epoll fd that is readable when any of the ring buffers has samples.
+    #[inline]
+    #[must_use]
+    pub fn epoll_fd(&self) -> RawFd {
+        unsafe { libbpf_sys::ring_buffer__epoll_fd(self.rb.as_ptr()) }
+    }
+}
+
+impl AsRawFd for RingBufConsumer<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll_fd()
+    }
+}
+
+impl AsFd for RingBufConsumer<'_> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.epoll_fd()) }
+    }
+}
+
+impl Drop for RingBufConsumer<'_> {
+    fn drop(&mut self) {
+        unsafe { libbpf_sys::ring_buffer__free(self.rb.as_ptr()) };
+    }
+}
+
+/// Consumer of a perf buffer, with one buffer per CPU. Samples that arrive
+/// while a CPU's buffer is full are lost, and counted in [PerfBufConsumer::lost].
+pub struct PerfBufConsumer<'cb> {
+    pb: NonNull<libbpf_sys::perf_buffer>,
+    callbacks: Box<PerfCallbacks<'cb>>,
+    // libbpf removes the buffers from the map through this fd when it frees
+    // them, so it has to stay open as long as the consumer.
+    _map: OwnedFd,
+}
+
+struct PerfCallbacks<'cb> {
+    sample: PerfSampleCallback<'cb>,
+    lost: Option<PerfLostCallback<'cb>>,
+    lost_count: u64,
+}
+
+impl<'cb> PerfBufConsumer<'cb> {
+    /// Consume samples from a loaded perf event array, with `page_count`
+    /// pages of buffer per CPU. The callback is given the CPU each sample was
+    /// sent from.
+    pub fn new<F: FnMut(i32, &[u8]) + 'cb>(
+        map: &Map<'_>,
+        page_count: usize,
+        callback: F,
+    ) -> Result<Self> {
+        check_map_type(
+            map,
+            libbpf_sys::BPF_MAP_TYPE_PERF_EVENT_ARRAY,
+            "perf_event_array",
+        )?;
+        if !page_count.is_power_of_two() {
+            return Err(Error::InvalidArgument(
+                "perf buffer page count must be a power of two",
+            ));
+        }
+        let buffer_error = |errno| Error::Buffer {
+            errno,
+            map: map.name(),
+        };
+
+        let map_fd = dup_fd(map.fd()).map_err(buffer_error)?;
+        let mut callbacks = Box::new(PerfCallbacks {
+            sample: Box::new(callback),
+            lost: None,
+            lost_count: 0,
+        });
+        let pb = unsafe {
+            libbpf_sys::perf_buffer__new(
+                map_fd.as_raw_fd(),
+                page_count as _,
+                Some(perf_sample),
+                Some(perf_lost),
+                &mut *callbacks as *mut PerfCallbacks as _,
+                null(),
+            )
+        };
+        match NonNull::new(pb) {
+            Some(pb) => Ok(PerfBufConsumer {
+                pb,
+                callbacks,
+                _map: map_fd,
+            }),
+            None => Err(buffer_error(Errno::last())),
+        }
+    }
+
+    /// Also call `callback` with the CPU and the number of samples lost each
+    /// time a full buffer drops samples.
+    pub fn on_lost<F: FnMut(i32, u64) + 'cb>(&mut self, callback: F) {
+        self.callbacks.lost = Some(Box::new(callback));
+    }
+
+    /// Total number of samples lost so far.
+    #[inline]
+    #[must_use]
+    pub fn lost(&self) -> u64 {
+        self.callbacks.lost_count
+    }
+
+    /// Number of per-CPU buffers.
+    #[must_use]
+    pub fn buffer_count(&self) -> usize {
+        unsafe { libbpf_sys::perf_buffer__buffer_cnt(self.pb.as_ptr()) as usize }
+    }
+
+    /// Wait up to `timeout` for samples, or until there are some if it is
+    /// `None`, and pass them to the callback. Returns how many buffers had
+    /// samples, which is zero if the wait was interrupted by a signal.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<usize> {
+        poll_result(unsafe { libbpf_sys::perf_buffer__poll(self.pb.as_ptr(), timeout_ms(timeout)) })
+    }
+
+    /// Pass the samples that are ready to the callback without waiting.
+    pub fn consume(&mut self) -> Result<usize> {
+        poll_result(unsafe { libbpf_sys::perf_buffer__consume(self.pb.as_ptr()) })
+    }
+
+    /// An epoll fd that is readable when any CPU's buffer has samples.
+    #[inline]
+    #[must_use]
+    pub fn epoll_fd(&self) -> RawFd {
+        unsafe { libbpf_sys::perf_buffer__epoll_fd(self.pb.as_ptr()) }
+    }
+}
+
+impl AsRawFd for PerfBufConsumer<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll_fd()
+    }
+}
+
+impl AsFd for PerfBufConsumer<'_> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.epoll_fd()) }
+    }
+}
+
+impl Drop for PerfBufConsumer<'_> {
+    fn drop(&mut self) {
+        unsafe { libbpf_sys::perf_buffer__free(self.pb.as_ptr()) };
+    }
+}
+
+unsafe extern "C" fn ring_sample(
+    ctx: *mut libc::c_void,
+    data: *mut libc::c_void,
+    size: libbpf_sys::size_t,
+) -> libc::c_int {
+    let callback = &mut *(ctx as *mut RingBufCallback);
+    callback(std::slice::from_raw_parts(data as *const u8, size as usize));
+    0
+}
+
+unsafe extern "C" fn perf_sample(
+    ctx: *mut libc::c_void,
+    cpu: libc::c_int,
+    data: *mut libc::c_void,
+    size: u32,
+) {
+    let callbacks = &mut *(ctx as *mut PerfCallbacks);
+    (callbacks.sample)(
+        cpu,
+        std::slice::from_raw_parts(data as *const u8, size as usize),
+    );
+}
+
+unsafe extern "C" fn perf_lost(ctx: *mut libc::c_void, cpu: libc::c_int, count: u64) {
+    let callbacks = &mut *(ctx as *mut PerfCallbacks);
+    callbacks.lost_count += count;
+    if let Some(lost) = &mut callbacks.lost {
+        lost(cpu, count);
+    }
+}
+
+fn check_map_type(
+    map: &Map<'_>,
+    map_type: libbpf_sys::bpf_map_type,
+    expected: &'static str,
+) -> Result<()> {
+    match map.map_type() {
+        actual if actual != map_type => Err(Error::MapType {
+            map: map.name(),
+            expected,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Convert a timeout to the milliseconds epoll takes, where -1 waits forever.
+#[must_use]
+fn timeout_ms(timeout: Option<Duration>) -> i32 {
+    match timeout {
+        Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+        None => -1,
+    }
+}
+
+fn poll_result(ret: i32) -> Result<usize> {
+    match ret {
+        ret if ret == -libc::EINTR => Ok(0),
+        ret if ret < 0 => Err(Error::Poll(Errno::from_ret(ret))),
+        count => Ok(count as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_ms() {
+        assert_eq!(-1, timeout_ms(None));
+        assert_eq!(250, timeout_ms(Some(Duration::from_millis(250))));
+        assert_eq!(i32::MAX, timeout_ms(Some(Duration::MAX)));
+    }
+
+    #[test]
+    fn test_perf_lost_counts_without_callback() {
+        let mut callbacks = PerfCallbacks {
+            sample: Box::new(|_, _| {}),
+            lost: None,
+            lost_count: 0,
+        };
+        let ctx = &mut callbacks as *mut PerfCallbacks as *mut libc::c_void;
+        unsafe {
+            perf_lost(ctx, 0, 3);
+            perf_lost(ctx, 1, 4);
+        }
+        assert_eq!(7, callbacks.lost_count);
+    }
+}
@@ -0,0 +1,220 @@
+//! Opening BPF objects with options, from a file or from a buffer, and
+//! creating maps that don't belong to an object.
+//!
+//! ```ignore
+//! let mut obj = ObjectBuilder::new()
+//!     .name("filter")
+//!     .btf_custom_path("/boot/vmlinux.btf")
+//!     .open_file("filter.bpf.o")?;
+//! obj.set_global("allowed_vlan", &100u16)?;
+//! obj.load()?;
+//!
+//! let rules = MapBuilder::new(BPF_MAP_TYPE_HASH, 8, 4, 1024)
+//!     .name("tenant_rules")
+//!     .flags(BPF_F_NO_PREALLOC)
+//!     .build()?;
+//! ```
+use std::ffi::CString;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::ptr::null;
+
+use crate::{path_to_cstring, Errno, Error, Map, Object, Result};
+
+/// Options for opening an [Object]. Everything is copied by libbpf while
+/// opening, so a builder can be reused for several objects.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ObjectBuilder {
+    name: Option<String>,
+    btf_custom_path: Option<PathBuf>,
+    kconfig: Option<String>,
+    pin_root: Option<PathBuf>,
+    verifier_log: Option<usize>,
+}
+
+impl ObjectBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Name of the object, which prefixes the names of its `.rodata`,
+    /// `.data` and `.bss` maps. Defaults to the file name, or to a name
+    /// derived from the buffer's address.
+    #[must_use]
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Kernel BTF to relocate against instead of `/sys/kernel/btf/vmlinux`,
+    /// for kernels built without `CONFIG_DEBUG_INFO_BTF`.
+    #[must_use]
+    pub fn btf_custom_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.btf_custom_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Kconfig lines, such as `CONFIG_HZ=1000`, that take precedence over the
+    /// running kernel's config for `extern` `CONFIG_*` variables. Read a file
+    /// with [std::fs::read_to_string] to use a custom config.
+    #[must_use]
+    pub fn kconfig<S: Into<String>>(mut self, kconfig: S) -> Self {
+        self.kconfig = Some(kconfig.into());
+        self
+    }
+
+    /// Directory that maps declared with `LIBBPF_PIN_BY_NAME` are pinned
+    /// under, instead of `/sys/fs/bpf`.
+    #[must_use]
+    pub fn pin_root<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.pin_root = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Keep the verifier log of a rejected program in a buffer of `size`
+    /// bytes, so [Error::verifier_log](crate::Error::verifier_log) returns it.
+    /// 1 MiB is usually enough, and a log that doesn't fit fails the load
+    /// with `ENOSPC`. Without a buffer, libbpf logs the verifier's output as a
+    /// warning.
+    #[must_use]
+    pub fn verifier_log(mut self, size: usize) -> Self {
+        self.verifier_log = Some(size);
+        self
+    }
+
+    /// Open the BPF ELF object file at `path`.
+    pub fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Object> {
+        let path = path_to_cstring(path.as_ref())?;
+        self.open(|opts| unsafe { libbpf_sys::bpf_object__open_file(path.as_ptr(), opts) })
+    }
+
+    /// Open a BPF ELF object from a buffer, such as one embedded with
+    /// `include_bytes!`.
+    pub fn open_memory(&self, obj_buf: &[u8]) -> Result<Object> {
+        self.open(|opts| unsafe {
+            libbpf_sys::bpf_object__open_mem(obj_buf.as_ptr() as _, obj_buf.len() as u64, opts)
+        })
+    }
+
+    fn open(
+        &self,
+        open: impl FnOnce(&libbpf_sys::bpf_object_open_opts) -> *mut libbpf_sys::bpf_object,
+    ) -> Result<Object> {
+        let name = self.name.as_deref().map(to_cstring).transpose()?;
+        let kconfig = self.kconfig.as_deref().map(to_cstring).transpose()?;
+        let btf_custom_path = self
+            .btf_custom_path
+            .as_deref()
+            .map(path_to_cstring)
+            .transpose()?;
+        let pin_root = self.pin_root.as_deref().map(path_to_cstring).transpose()?;
+
+        let opts = libbpf_sys::bpf_object_open_opts {
+            object_name: as_ptr(&name),
+            kconfig: as_ptr(&kconfig),
+            btf_custom_path: as_ptr(&btf_custom_path),
+            pin_root_path: as_ptr(&pin_root),
+            ..Default::default()
+        };
+        Object::open(opts, self.verifier_log, open)
+    }
+}
+
+/// Options for creating a standalone map with `bpf_map_create`. The map lives
+/// as long as the returned [Map] or a pin of it, independently of any
+/// [Object].
+#[derive(Debug, Clone)]
+pub struct MapBuilder<'inner> {
+    map_type: libbpf_sys::bpf_map_type,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    name: Option<String>,
+    flags: u32,
+    inner_map: Option<BorrowedFd<'inner>>,
+}
+
+impl<'inner> MapBuilder<'inner> {
+    #[must_use]
+    pub fn new(
+        map_type: libbpf_sys::bpf_map_type,
+        key_size: u32,
+        value_size: u32,
+        max_entries: u32,
+    ) -> Self {
+        MapBuilder {
+            map_type,
+            key_size,
+            value_size,
+            max_entries,
+            name: None,
+            flags: 0,
+            inner_map: None,
+        }
+    }
+
+    /// Name of the map, which the kernel truncates to 15 bytes.
+    #[must_use]
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// `BPF_F_*` flags, such as `BPF_F_NO_PREALLOC` for hash maps or
+    /// `BPF_F_INNER_MAP` for arrays stored in an `ARRAY_OF_MAPS` with others
+    /// of different sizes.
+    #[must_use]
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Template for the maps stored in an `ARRAY_OF_MAPS` or `HASH_OF_MAPS`,
+    /// which the kernel requires to create one. Only its type, sizes and
+    /// flags are kept, so any map of the right shape will do.
+    #[must_use]
+    pub fn inner_map(mut self, inner: &'inner Map) -> Self {
+        self.inner_map = (inner.fd() >= 0).then(|| unsafe { BorrowedFd::borrow_raw(inner.fd()) });
+        self
+    }
+
+    /// Create the map.
+    pub fn build(&self) -> Result<Map<'static>> {
+        let name = self.name.as_deref().map(to_cstring).transpose()?;
+        let opts = libbpf_sys::bpf_map_create_opts {
+            sz: size_of::<libbpf_sys::bpf_map_create_opts>() as _,
+            map_flags: self.flags,
+            inner_map_fd: self.inner_map.map_or(0, |fd| fd.as_raw_fd() as u32),
+            ..Default::default()
+        };
+        let fd = unsafe {
+            libbpf_sys::bpf_map_create(
+                self.map_type,
+                as_ptr(&name),
+                self.key_size,
+                self.value_size,
+                self.max_entries,
+                &opts,
+            )
+        };
+        let create_error = |errno| Error::MapCreate {
+            errno,
+            map: self.name.clone().unwrap_or_default(),
+        };
+        match fd {
+            fd if fd < 0 => Err(create_error(Errno::from_ret(fd))),
+            fd => Map::from_fd(unsafe { OwnedFd::from_raw_fd(fd) }).map_err(create_error),
+        }
+    }
+}
+
+fn to_cstring(s: &str) -> Result<CString> {
+    CString::new(s).map_err(|_| Error::InvalidArgument("could not convert to CString"))
+}
+
+#[must_use]
+fn as_ptr(s: &Option<CString>) -> *const libc::c_char {
+    s.as_ref().map_or(null(), |s| s.as_ptr())
+}
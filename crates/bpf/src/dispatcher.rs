@@ -0,0 +1,862 @@
+//! Running several XDP programs on one interface through a dispatcher that
+//! follows libxdp's protocol, so programs added by `xdp-loader` and by
+//! [XdpDispatcher] can share an interface.
+//!
+//! The dispatcher is an XDP program with ten slots, and each program added to
+//! it is loaded as an extension that replaces a slot with `freplace`. The
+//! programs run in order of priority, and a program's result only lets the
+//! next one run if it is one of its chain call actions. The programs and their
+//! links are pinned under `/sys/fs/bpf/xdp/dispatch-<ifindex>-<id>`, so they
+//! stay attached after the process exits, until they are detached.
+//!
+//! ```ignore
+//! let mut filter = ObjectBuilder::new().open_file("ddos_filter.bpf.o")?;
+//! let config = RunConfig::new().priority(10);
+//! XdpDispatcher::attach(ifindex, XdpMode::Native, &mut filter, "ddos_filter", config)?;
+//!
+//! let mut redirect = ObjectBuilder::new().open_file("redirect.bpf.o")?;
+//! let config = RunConfig::from_object(&redirect, "xsk_redirect")?;
+//! let dispatcher =
+//!     XdpDispatcher::attach(ifindex, XdpMode::Native, &mut redirect, "xsk_redirect", config)?;
+//!
+//! for prog in dispatcher.programs() {
+//!     println!("{} {} {}", prog.id(), prog.name(), prog.priority());
+//! }
+//! ```
+use std::ffi::CString;
+use std::fs::{DirBuilder, File};
+use std::io::ErrorKind;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+
+use crate::global::{btf_kind, btf_vlen, find_var};
+use crate::link::{xdp_attach, xdp_detach};
+use crate::pin::{obj_get, pin_result};
+use crate::{
+    cstr_to_string, obj_get_info, path_to_cstring, Array, Errno, Error, Map, Object, Pod, Program,
+    Result, XdpAction, XdpMode,
+};
+
+/// The compiled BPF object of the dispatcher, built from
+/// `bpf/xdp_dispatcher.c`.
+const DISPATCHER: &[u8] = include_bytes!("../bpf/xdp_dispatcher.o");
+const DISPATCHER_PROGRAM: &str = "xdp_dispatcher";
+
+/// Version of libxdp's protocol that the dispatcher implements, and the magic
+/// number its config starts with.
+const DISPATCHER_VERSION: u32 = 2;
+const DISPATCHER_MAGIC: u8 = 236;
+/// What an empty slot returns. It is a chain call action of every slot.
+const DISPATCHER_RETVAL: u32 = 31;
+
+const METADATA_SECTION: &str = "xdp_metadata";
+const RUN_CONFIG_SECTION: &str = ".xdp_run_config";
+
+/// Number of programs a dispatcher can run.
+pub const MAX_PROGRAMS: usize = 10;
+/// Priority of programs whose run config does not set one.
+pub const DEFAULT_PRIORITY: u32 = 50;
+
+/// The dispatcher's `struct xdp_dispatcher_config`, which it is loaded with.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+struct DispatcherConfig {
+    magic: u8,
+    dispatcher_version: u8,
+    num_progs_enabled: u8,
+    is_xdp_frags: u8,
+    chain_call_actions: [u32; MAX_PROGRAMS],
+    run_prios: [u32; MAX_PROGRAMS],
+    program_flags: [u32; MAX_PROGRAMS],
+}
+
+// SAFETY: the config is made of integers, with no padding.
+unsafe impl Pod for DispatcherConfig {}
+
+/// Where a program runs in a dispatcher, and which of its results let the
+/// next program run. Defaults to priority 50, running the next program after
+/// `XDP_PASS`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct RunConfig {
+    priority: u32,
+    chain_call_actions: u32,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            priority: DEFAULT_PRIORITY,
+            chain_call_actions: 1 << libbpf_sys::XDP_PASS,
+        }
+    }
+}
+
+impl RunConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The run config that an object declares for a program with libxdp's
+    /// `XDP_RUN_CONFIG` macro, which `xdp-loader` reads too. Programs that do
+    /// not declare one get the defaults.
+    pub fn from_object(obj: &Object, prog: &str) -> Result<Self> {
+        match unsafe { libbpf_sys::bpf_object__btf(obj.obj) } {
+            btf if btf.is_null() => Ok(RunConfig::new()),
+            btf => unsafe { run_config_from_btf(btf, prog) },
+        }
+    }
+
+    /// Programs with a lower priority run first, and programs with the same
+    /// priority run in order of name.
+    #[must_use]
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Choose whether the next program runs when this one returns `action`.
+    #[must_use]
+    pub fn chain_call_action(mut self, action: XdpAction, chain: bool) -> Self {
+        let bit = 1u32.checked_shl(action.into()).unwrap_or(0);
+        match chain {
+            true => self.chain_call_actions |= bit,
+            false => self.chain_call_actions &= !bit,
+        }
+        self
+    }
+}
+
+/// A program running in a dispatcher.
+#[derive(Debug)]
+pub struct DispatchedProgram {
+    prog: Program<'static>,
+    link: OwnedFd,
+    id: u32,
+    run_config: RunConfig,
+    flags: u32,
+}
+
+impl DispatchedProgram {
+    fn new(
+        prog: Program<'static>,
+        link: OwnedFd,
+        run_config: RunConfig,
+        flags: u32,
+    ) -> Result<Self> {
+        let info: libbpf_sys::bpf_prog_info = obj_get_info(prog.fd()).map_err(Error::Errno)?;
+        Ok(DispatchedProgram {
+            prog,
+            link,
+            id: info.id,
+            run_config,
+            flags,
+        })
+    }
+
+    /// Open the program pinned for `slot` of a dispatcher.
+    fn from_pinned(dir: &Path, slot: usize, config: &DispatcherConfig) -> Result<Self> {
+        let prog = Program::from_pinned(dir.join(format!("prog{slot}-prog")))?;
+        let link = obj_get(&dir.join(format!("prog{slot}-link")))?;
+        let run_config = RunConfig {
+            priority: config.run_prios[slot],
+            chain_call_actions: config.chain_call_actions[slot] & !(1 << DISPATCHER_RETVAL),
+        };
+        DispatchedProgram::new(prog, link, run_config, config.program_flags[slot])
+    }
+
+    #[must_use]
+    pub fn program(&self) -> &Program<'static> {
+        &self.prog
+    }
+
+    /// Id of the program, which [XdpDispatcher::detach] takes.
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.prog.name()
+    }
+
+    #[must_use]
+    pub fn priority(&self) -> u32 {
+        self.run_config.priority
+    }
+
+    /// The results of the program that let the next program run.
+    #[must_use]
+    pub fn chain_call_actions(&self) -> Vec<XdpAction> {
+        (0..u32::BITS)
+            .filter(|action| self.run_config.chain_call_actions & (1 << action) != 0)
+            .map(XdpAction::from)
+            .collect()
+    }
+}
+
+/// The dispatcher attached to an interface, with the programs running in it
+/// in the order they run. Unlike a [crate::LinkedProgram], dropping it leaves
+/// the programs attached, until they are removed with [XdpDispatcher::detach].
+#[derive(Debug)]
+pub struct XdpDispatcher {
+    ifindex: u32,
+    mode: XdpMode,
+    id: u32,
+    prog: Program<'static>,
+    programs: Vec<DispatchedProgram>,
+}
+
+/// What is attached to an interface.
+enum Attached {
+    None,
+    Program,
+    Dispatcher(Box<XdpDispatcher>),
+}
+
+/// A program to run in a new dispatcher.
+enum Slot<'a> {
+    /// A program of the dispatcher being replaced, which is attached to the new
+    /// one as well.
+    Loaded(&'a DispatchedProgram),
+    /// A program of an object that is loaded as an extension of the new
+    /// dispatcher.
+    New {
+        obj: &'a mut Object,
+        name: &'a str,
+        run_config: RunConfig,
+        flags: u32,
+    },
+}
+
+impl Slot<'_> {
+    #[must_use]
+    fn run_config(&self) -> RunConfig {
+        match self {
+            Slot::Loaded(prog) => prog.run_config,
+            Slot::New { run_config, .. } => *run_config,
+        }
+    }
+
+    #[must_use]
+    fn flags(&self) -> u32 {
+        match self {
+            Slot::Loaded(prog) => prog.flags,
+            Slot::New { flags, .. } => *flags,
+        }
+    }
+
+    /// Programs run by priority then name, as libxdp orders them. Loaded
+    /// programs only have the name the kernel truncated, so new programs are
+    /// ordered by the same truncated name.
+    #[must_use]
+    fn order(&self) -> (u32, String) {
+        let name = match self {
+            Slot::Loaded(prog) => prog.name(),
+            Slot::New { name, .. } => name
+                .chars()
+                .take(libbpf_sys::BPF_OBJ_NAME_LEN as usize - 1)
+                .collect(),
+        };
+        (self.run_config().priority, name)
+    }
+}
+
+impl XdpDispatcher {
+    /// The dispatcher attached to the interface, or `None` if no program or a
+    /// program that is not a dispatcher is attached.
+    pub fn from_ifindex(ifindex: u32) -> Result<Option<Self>> {
+        let _lock = Lock::acquire()?;
+        match XdpDispatcher::query(ifindex)? {
+            Attached::Dispatcher(dispatcher) => Ok(Some(*dispatcher)),
+            Attached::None | Attached::Program => Ok(None),
+        }
+    }
+
+    /// Add the program `name` of `obj` to the dispatcher on the interface, or
+    /// attach a new dispatcher in `mode` if there is none. The object must not
+    /// have been loaded, since the program is loaded as an extension of the
+    /// dispatcher. Fails if a program that is not a dispatcher is attached.
+    pub fn attach(
+        ifindex: u32,
+        mode: XdpMode,
+        obj: &mut Object,
+        name: &str,
+        run_config: RunConfig,
+    ) -> Result<Self> {
+        let _lock = Lock::acquire()?;
+        let old = match XdpDispatcher::query(ifindex)? {
+            Attached::None => None,
+            Attached::Program => {
+                return Err(Error::Attach {
+                    errno: Errno(libc::EEXIST),
+                    program: name.to_owned(),
+                    ifindex,
+                })
+            }
+            Attached::Dispatcher(old) => Some(old),
+        };
+        let mode = match &old {
+            Some(old) if mode != XdpMode::Auto && mode != old.mode => {
+                return Err(Error::InvalidArgument(
+                    "the dispatcher is attached in a different mode",
+                ))
+            }
+            Some(old) => old.mode,
+            None => mode,
+        };
+
+        let mut slots: Vec<Slot> = old
+            .iter()
+            .flat_map(|old| old.programs.iter().map(Slot::Loaded))
+            .collect();
+        if slots.len() == MAX_PROGRAMS {
+            return Err(Error::InvalidArgument(
+                "a dispatcher runs at most 10 programs",
+            ));
+        }
+        let flags = unsafe { libbpf_sys::bpf_program__flags(obj.find_program(name)?.prog) };
+        slots.push(Slot::New {
+            obj,
+            name,
+            run_config,
+            flags,
+        });
+        XdpDispatcher::replace(ifindex, mode, old.as_deref(), slots)
+    }
+
+    /// Remove the program with id `prog_id` from the dispatcher on the
+    /// interface. The dispatcher is detached along with its last program, and
+    /// `None` is returned.
+    pub fn detach(ifindex: u32, prog_id: u32) -> Result<Option<Self>> {
+        let _lock = Lock::acquire()?;
+        let not_found = || Error::ProgramNotFound(format!("id {prog_id}"));
+        let Attached::Dispatcher(old) = XdpDispatcher::query(ifindex)? else {
+            return Err(not_found());
+        };
+
+        let slots: Vec<Slot> = old
+            .programs
+            .iter()
+            .filter(|prog| prog.id != prog_id)
+            .map(Slot::Loaded)
+            .collect();
+        match slots.len() {
+            len if len == old.programs.len() => Err(not_found()),
+            0 => old.remove().map(|_| None),
+            _ => XdpDispatcher::replace(ifindex, old.mode, Some(&old), slots).map(Some),
+        }
+    }
+
+    /// Detach the dispatcher on the interface, with every program in it. Does
+    /// nothing if no dispatcher is attached.
+    pub fn detach_all(ifindex: u32) -> Result<()> {
+        let _lock = Lock::acquire()?;
+        match XdpDispatcher::query(ifindex)? {
+            Attached::Dispatcher(dispatcher) => dispatcher.remove(),
+            Attached::None | Attached::Program => Ok(()),
+        }
+    }
+
+    #[must_use]
+    pub fn ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> XdpMode {
+        self.mode
+    }
+
+    /// The dispatcher program itself.
+    #[must_use]
+    pub fn program(&self) -> &Program<'static> {
+        &self.prog
+    }
+
+    /// The programs in the dispatcher, in the order they run.
+    #[must_use]
+    pub fn programs(&self) -> &[DispatchedProgram] {
+        &self.programs
+    }
+
+    /// Find out what is attached to the interface, opening the programs of a
+    /// dispatcher from their pins. Must be called with the lock held.
+    fn query(ifindex: u32) -> Result<Attached> {
+        let Some((id, mode)) = attached_program(ifindex)? else {
+            return Ok(Attached::None);
+        };
+        let prog = Program::from_id(id)?;
+        let info: libbpf_sys::bpf_prog_info = obj_get_info(prog.fd()).map_err(Error::Errno)?;
+        // Dispatchers are recognized by their metadata, as libxdp does.
+        let version = match info.btf_id {
+            0 => None,
+            btf_id => unsafe { metadata_version(KernelBtf::load(btf_id)?.0) },
+        };
+        let Some(version) = version else {
+            return Ok(Attached::Program);
+        };
+
+        let config = read_config(&prog)?;
+        if version != DISPATCHER_VERSION || config.magic != DISPATCHER_MAGIC {
+            return Err(Error::DispatcherVersion { ifindex, version });
+        }
+        let dir = pin_dir(ifindex, id);
+        let programs = (0..config.num_progs_enabled as usize)
+            .map(|slot| DispatchedProgram::from_pinned(&dir, slot, &config))
+            .collect::<Result<_>>()?;
+        Ok(Attached::Dispatcher(Box::new(XdpDispatcher {
+            ifindex,
+            mode,
+            id,
+            prog,
+            programs,
+        })))
+    }
+
+    /// Load a dispatcher that runs `slots`, attach it in place of `old`, and
+    /// move the pins over to it. Must be called with the lock held.
+    fn replace(
+        ifindex: u32,
+        mode: XdpMode,
+        old: Option<&Self>,
+        mut slots: Vec<Slot>,
+    ) -> Result<Self> {
+        slots.sort_by_cached_key(Slot::order);
+
+        // The dispatcher only handles multi-buffer packets if every program
+        // does, and new programs are loaded to match it.
+        let frags = slots
+            .iter()
+            .all(|slot| slot.flags() & libbpf_sys::BPF_F_XDP_HAS_FRAGS != 0);
+        let mut config = DispatcherConfig {
+            magic: DISPATCHER_MAGIC,
+            dispatcher_version: DISPATCHER_VERSION as u8,
+            num_progs_enabled: slots.len() as u8,
+            is_xdp_frags: frags as u8,
+            ..Default::default()
+        };
+        for (i, slot) in slots.iter_mut().enumerate() {
+            if let Slot::New { flags, .. } = slot {
+                if !frags {
+                    *flags &= !libbpf_sys::BPF_F_XDP_HAS_FRAGS;
+                }
+            }
+            let run_config = slot.run_config();
+            config.chain_call_actions[i] = run_config.chain_call_actions | 1 << DISPATCHER_RETVAL;
+            config.run_prios[i] = run_config.priority;
+            config.program_flags[i] = slot.flags();
+        }
+
+        let mut obj = Object::create(DISPATCHER)?;
+        obj.set_global("conf", &config)?;
+        if frags {
+            let prog = obj.find_program_mut(DISPATCHER_PROGRAM)?;
+            let ret = unsafe {
+                libbpf_sys::bpf_program__set_flags(prog.prog.prog, libbpf_sys::BPF_F_XDP_HAS_FRAGS)
+            };
+            prog.configure_result(ret)?;
+        }
+        obj.load()?;
+        let dispatcher = obj.find_program(DISPATCHER_PROGRAM)?;
+        let info: libbpf_sys::bpf_prog_info =
+            obj_get_info(dispatcher.fd()).map_err(Error::Errno)?;
+        let btf = KernelBtf::load(info.btf_id)?;
+
+        let mut programs = Vec::with_capacity(slots.len());
+        for (i, slot) in slots.into_iter().enumerate() {
+            let func = format!("prog{i}");
+            let (prog, run_config, flags) = match slot {
+                Slot::Loaded(prog) => (prog.prog.try_clone()?, prog.run_config, prog.flags),
+                Slot::New {
+                    obj,
+                    name,
+                    run_config,
+                    flags,
+                } => {
+                    let mut prog = obj.find_program_mut(name)?;
+                    prog.set_prog_type(libbpf_sys::BPF_PROG_TYPE_EXT)?;
+                    // Extensions must not have an attach type.
+                    prog.set_expected_attach_type(0)?;
+                    prog.set_attach_target(dispatcher.fd(), &func)?;
+                    let ret = unsafe { libbpf_sys::bpf_program__set_flags(prog.prog.prog, flags) };
+                    prog.configure_result(ret)?;
+                    obj.load()?;
+                    (obj.find_program(name)?.try_clone()?, run_config, flags)
+                }
+            };
+            let link = freplace(&prog, &dispatcher, btf.func_id(&func)?, ifindex)?;
+            programs.push(DispatchedProgram::new(prog, link, run_config, flags)?);
+        }
+
+        let mut new = XdpDispatcher {
+            ifindex,
+            mode,
+            id: info.id,
+            prog: dispatcher.try_clone()?,
+            programs,
+        };
+        new.pin()?;
+        let (flags, old_fd) = match old {
+            Some(old) => (
+                mode.flags() | libbpf_sys::XDP_FLAGS_REPLACE,
+                Some(old.prog.fd()),
+            ),
+            None => (mode.flags() | libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST, None),
+        };
+        let ret = xdp_attach(ifindex, new.prog.fd(), flags, old_fd);
+        if ret < 0 {
+            let _ = new.unpin();
+            return Err(Error::Attach {
+                errno: Errno::from_ret(ret),
+                program: DISPATCHER_PROGRAM.to_owned(),
+                ifindex,
+            });
+        }
+
+        // The kernel picks the mode when none is given.
+        if let Some((_, mode)) = attached_program(ifindex)? {
+            new.mode = mode;
+        }
+        if let Some(old) = old {
+            if let Err(err) = old.unpin() {
+                log::warn!("failed to unpin the replaced dispatcher: {err}");
+            }
+        }
+        Ok(new)
+    }
+
+    /// Detach the dispatcher from the interface and remove its pins.
+    fn remove(&self) -> Result<()> {
+        match xdp_detach(self.ifindex, self.prog.fd(), self.mode.flags()) {
+            ret if ret < 0 => Err(Error::Detach {
+                errno: Errno::from_ret(ret),
+                ifindex: self.ifindex,
+            }),
+            _ => self.unpin(),
+        }
+    }
+
+    /// Pin the programs and links under the dispatcher's directory, as
+    /// `prog<slot>-prog` and `prog<slot>-link`.
+    fn pin(&self) -> Result<()> {
+        let dir = pin_dir(self.ifindex, self.id);
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|err| dir_error(err, &dir))?;
+
+        let result = self
+            .programs
+            .iter()
+            .enumerate()
+            .try_for_each(|(slot, prog)| {
+                let path = dir.join(format!("prog{slot}-link"));
+                let c_path = path_to_cstring(&path)?;
+                let ret =
+                    unsafe { libbpf_sys::bpf_obj_pin(prog.link.as_raw_fd(), c_path.as_ptr()) };
+                pin_result(ret, &path)?;
+                prog.prog.pin(dir.join(format!("prog{slot}-prog")))
+            });
+        if result.is_err() {
+            let _ = self.unpin();
+        }
+        result
+    }
+
+    /// Remove the dispatcher's pins, ignoring those that are already gone.
+    fn unpin(&self) -> Result<()> {
+        let dir = pin_dir(self.ifindex, self.id);
+        let ignore_missing = |result: std::io::Result<()>, path: &Path| match result {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(dir_error(err, path)),
+            _ => Ok(()),
+        };
+        for slot in 0..self.programs.len() {
+            for file in [format!("prog{slot}-link"), format!("prog{slot}-prog")] {
+                let path = dir.join(file);
+                ignore_missing(std::fs::remove_file(&path), &path)?;
+            }
+        }
+        ignore_missing(std::fs::remove_dir(&dir), &dir)
+    }
+}
+
+/// libxdp's lock on its bpffs directory, which serializes changes to
+/// dispatchers between processes. It is released when dropped.
+struct Lock {
+    _dir: File,
+}
+
+impl Lock {
+    fn acquire() -> Result<Lock> {
+        let dir = xdp_dir();
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(dir_error(err, &dir)),
+            _ => {}
+        }
+        let file = File::open(&dir).map_err(|err| dir_error(err, &dir))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } < 0 {
+            return Err(dir_error(std::io::Error::last_os_error(), &dir));
+        }
+        Ok(Lock { _dir: file })
+    }
+}
+
+/// BTF of a loaded program, read from the kernel.
+struct KernelBtf(*mut libbpf_sys::btf);
+
+impl KernelBtf {
+    fn load(id: u32) -> Result<Self> {
+        match unsafe { libbpf_sys::btf__load_from_kernel_by_id(id) } {
+            btf if btf.is_null() => Err(Error::Errno(Errno::last())),
+            btf => Ok(KernelBtf(btf)),
+        }
+    }
+
+    /// Id of the function `name`, which `freplace` targets.
+    fn func_id(&self, name: &str) -> Result<u32> {
+        let c_name = CString::new(name)
+            .map_err(|_| Error::InvalidArgument("could not convert to CString"))?;
+        match unsafe {
+            libbpf_sys::btf__find_by_name_kind(self.0, c_name.as_ptr(), libbpf_sys::BTF_KIND_FUNC)
+        } {
+            ret if ret < 0 => Err(Error::ProgramNotFound(name.to_owned())),
+            id => Ok(id as u32),
+        }
+    }
+}
+
+impl Drop for KernelBtf {
+    fn drop(&mut self) {
+        unsafe { libbpf_sys::btf__free(self.0) };
+    }
+}
+
+/// Directory that libxdp keeps its pins and lock in, on the bpffs mount in
+/// `$LIBXDP_BPFFS` or `/sys/fs/bpf`.
+#[must_use]
+fn xdp_dir() -> PathBuf {
+    std::env::var_os("LIBXDP_BPFFS")
+        .map_or_else(|| PathBuf::from("/sys/fs/bpf"), PathBuf::from)
+        .join("xdp")
+}
+
+#[must_use]
+fn pin_dir(ifindex: u32, dispatcher_id: u32) -> PathBuf {
+    xdp_dir().join(format!("dispatch-{ifindex}-{dispatcher_id}"))
+}
+
+#[must_use]
+fn dir_error(err: std::io::Error, path: &Path) -> Error {
+    Error::Pin {
+        errno: Errno(err.raw_os_error().unwrap_or(libc::EIO)),
+        path: path.display().to_string(),
+    }
+}
+
+/// Id and mode of the program attached to the interface. Offloaded programs
+/// are ignored, since they cannot run a dispatcher.
+fn attached_program(ifindex: u32) -> Result<Option<(u32, XdpMode)>> {
+    let mut opts = libbpf_sys::bpf_xdp_query_opts {
+        sz: size_of::<libbpf_sys::bpf_xdp_query_opts>() as _,
+        ..Default::default()
+    };
+    match unsafe { libbpf_sys::bpf_xdp_query(ifindex as i32, 0, &mut opts) } {
+        ret if ret < 0 => Err(Error::Errno(Errno::from_ret(ret))),
+        _ => Ok(match (opts.drv_prog_id, opts.skb_prog_id) {
+            (0, 0) => None,
+            (0, id) => Some((id, XdpMode::Generic)),
+            (id, _) => Some((id, XdpMode::Native)),
+        }),
+    }
+}
+
+/// Read the config a dispatcher was loaded with from its `.rodata`, which is
+/// its only map.
+fn read_config(prog: &Program<'static>) -> Result<DispatcherConfig> {
+    let info: libbpf_sys::bpf_prog_info = obj_get_info(prog.fd()).map_err(Error::Errno)?;
+    let mut map_id = 0u32;
+    let mut info = libbpf_sys::bpf_prog_info {
+        nr_map_ids: info.nr_map_ids.min(1),
+        map_ids: &mut map_id as *mut u32 as u64,
+        ..Default::default()
+    };
+    let mut len = size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let ret = unsafe {
+        libbpf_sys::bpf_obj_get_info_by_fd(prog.fd(), &mut info as *mut _ as *mut _, &mut len)
+    };
+    if ret < 0 {
+        return Err(Error::Errno(Errno::from_ret(ret)));
+    }
+    if info.nr_map_ids != 1 {
+        return Err(Error::MapNotFound(".rodata".to_owned()));
+    }
+    let map = Map::from_id(map_id)?;
+    Array::<DispatcherConfig>::new(map)?.lookup(0)
+}
+
+/// Attach an extension program to the function `btf_id` of the dispatcher.
+fn freplace(prog: &Program, dispatcher: &Program, btf_id: u32, ifindex: u32) -> Result<OwnedFd> {
+    let opts = libbpf_sys::bpf_link_create_opts {
+        sz: size_of::<libbpf_sys::bpf_link_create_opts>() as _,
+        target_btf_id: btf_id,
+        ..Default::default()
+    };
+    // The attach type is ignored for extensions.
+    match unsafe { libbpf_sys::bpf_link_create(prog.fd(), dispatcher.fd(), 0, &opts) } {
+        ret if ret < 0 => Err(Error::Attach {
+            errno: Errno::from_ret(ret),
+            program: prog.name(),
+            ifindex,
+        }),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
+
+/// The protocol version in a dispatcher's metadata, or `None` if the program
+/// is not a dispatcher.
+///
+/// # Safety
+///
+/// `btf` must be a valid BTF object.
+#[must_use]
+unsafe fn metadata_version(btf: *const libbpf_sys::btf) -> Option<u32> {
+    let var = section_var(btf, METADATA_SECTION, "dispatcher_version")?;
+    btf_uint(btf, (*var).__bindgen_anon_1.type_)
+}
+
+/// Read the `XDP_RUN_CONFIG` declared for `prog`, a struct of `__uint` fields
+/// named `_<prog>` in the `.xdp_run_config` section.
+///
+/// # Safety
+///
+/// `btf` must be a valid BTF object.
+unsafe fn run_config_from_btf(btf: *const libbpf_sys::btf, prog: &str) -> Result<RunConfig> {
+    let mut run_config = RunConfig::new();
+    let Some(var) = section_var(btf, RUN_CONFIG_SECTION, &format!("_{prog}")) else {
+        return Ok(run_config);
+    };
+    let def = libbpf_sys::btf__type_by_id(btf, (*var).__bindgen_anon_1.type_);
+    if def.is_null() || btf_kind(&*def) != libbpf_sys::BTF_KIND_STRUCT {
+        return Err(Error::InvalidArgument("XDP_RUN_CONFIG is not a struct"));
+    }
+    // The struct's members follow its btf_type.
+    let members =
+        std::slice::from_raw_parts(def.add(1) as *const libbpf_sys::btf_member, btf_vlen(&*def));
+    for member in members {
+        let name = cstr_to_string(libbpf_sys::btf__name_by_offset(btf, member.name_off));
+        let value = btf_uint(btf, member.type_).ok_or(Error::InvalidArgument(
+            "XDP_RUN_CONFIG fields must be __uint",
+        ))?;
+        run_config = match (name.as_str(), action_from_name(&name)) {
+            ("priority", _) => run_config.priority(value),
+            (_, Some(action)) => run_config.chain_call_action(action, value != 0),
+            _ => return Err(Error::InvalidArgument("unknown field in XDP_RUN_CONFIG")),
+        };
+    }
+    Ok(run_config)
+}
+
+#[must_use]
+fn action_from_name(name: &str) -> Option<XdpAction> {
+    match name {
+        "XDP_ABORTED" => Some(XdpAction::Aborted),
+        "XDP_DROP" => Some(XdpAction::Drop),
+        "XDP_PASS" => Some(XdpAction::Pass),
+        "XDP_TX" => Some(XdpAction::Tx),
+        "XDP_REDIRECT" => Some(XdpAction::Redirect),
+        _ => None,
+    }
+}
+
+/// The `VAR` named `name` in the `DATASEC` named `section`.
+///
+/// # Safety
+///
+/// `btf` must be a valid BTF object.
+#[must_use]
+unsafe fn section_var(
+    btf: *const libbpf_sys::btf,
+    section: &str,
+    name: &str,
+) -> Option<*const libbpf_sys::btf_type> {
+    let c_section = CString::new(section).ok()?;
+    let datasec =
+        libbpf_sys::btf__find_by_name_kind(btf, c_section.as_ptr(), libbpf_sys::BTF_KIND_DATASEC);
+    if datasec < 0 {
+        return None;
+    }
+    let secinfo = find_var(btf, datasec as u32, name)?;
+    Some(libbpf_sys::btf__type_by_id(btf, secinfo.type_))
+}
+
+/// The value of a field declared with libbpf's `__uint(name, value)`, which
+/// is encoded in its type, `int (*)[value]`.
+///
+/// # Safety
+///
+/// `btf` must be a valid BTF object.
+#[must_use]
+unsafe fn btf_uint(btf: *const libbpf_sys::btf, type_id: u32) -> Option<u32> {
+    let ptr = libbpf_sys::btf__type_by_id(btf, type_id);
+    if ptr.is_null() || btf_kind(&*ptr) != libbpf_sys::BTF_KIND_PTR {
+        return None;
+    }
+    let array = libbpf_sys::btf__type_by_id(btf, (*ptr).__bindgen_anon_1.type_);
+    if array.is_null() || btf_kind(&*array) != libbpf_sys::BTF_KIND_ARRAY {
+        return None;
+    }
+    Some((*(array.add(1) as *const libbpf_sys::btf_array)).nelems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_matches_libxdp_layout() {
+        assert_eq!(124, size_of::<DispatcherConfig>());
+    }
+
+    #[test]
+    fn test_chain_call_actions() {
+        let run_config = RunConfig::new()
+            .chain_call_action(XdpAction::Drop, true)
+            .chain_call_action(XdpAction::Pass, false);
+
+        assert_eq!(1 << libbpf_sys::XDP_DROP, run_config.chain_call_actions);
+    }
+
+    #[test]
+    fn test_run_config_from_btf() {
+        unsafe {
+            let btf = libbpf_sys::btf__new_empty();
+            let int = libbpf_sys::btf__add_int(btf, c"int".as_ptr(), 4, 1);
+            let index = libbpf_sys::btf__add_int(btf, c"__ARRAY_SIZE_TYPE__".as_ptr(), 4, 0);
+            let uint = |value| {
+                let array = libbpf_sys::btf__add_array(btf, index, int, value);
+                libbpf_sys::btf__add_ptr(btf, array)
+            };
+            let (priority, drop) = (uint(10), uint(1));
+            let def = libbpf_sys::btf__add_struct(btf, c"".as_ptr(), 16);
+            libbpf_sys::btf__add_field(btf, c"priority".as_ptr(), priority, 0, 0);
+            libbpf_sys::btf__add_field(btf, c"XDP_DROP".as_ptr(), drop, 64, 0);
+            let var = libbpf_sys::btf__add_var(btf, c"_filter".as_ptr(), 1, def);
+            libbpf_sys::btf__add_datasec(btf, c".xdp_run_config".as_ptr(), 16);
+            libbpf_sys::btf__add_datasec_var_info(btf, var, 0, 16);
+
+            let run_config = run_config_from_btf(btf, "filter").unwrap();
+            assert_eq!(10, run_config.priority);
+            assert_eq!(
+                1 << libbpf_sys::XDP_PASS | 1 << libbpf_sys::XDP_DROP,
+                run_config.chain_call_actions
+            );
+            assert_eq!(RunConfig::new(), run_config_from_btf(btf, "other").unwrap());
+
+            libbpf_sys::btf__free(btf);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use std::fmt;
+
+use crate::tc::TcHook;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to open object: {errno}{}", Hint(hint::open(*.errno)))]
+    Open { errno: Errno },
+    #[error("failed to load object: {errno}{}", Hint(hint::load(*.errno)))]
+    Load {
+        errno: Errno,
+        verifier_log: Option<String>,
+    },
+    #[error("program \"{0}\" not found")]
+    ProgramNotFound(String),
+    #[error("map \"{0}\" not found")]
+    MapNotFound(String),
+    #[error("failed to attach program \"{program}\" to ifindex {ifindex}: {errno}{}", Hint(hint::attach(*.errno)))]
+    Attach {
+        errno: Errno,
+        program: String,
+        ifindex: u32,
+    },
+    #[error("failed to attach program \"{program}\" to tc {hook} on ifindex {ifindex}: {errno}{}", Hint(hint::tc_attach(*.errno)))]
+    TcAttach {
+        errno: Errno,
+        program: String,
+        ifindex: u32,
+        hook: TcHook,
+    },
+    #[error("failed to detach from ifindex {ifindex}: {errno}")]
+    Detach { errno: Errno, ifindex: u32 },
+    #[error("failed to create map \"{map}\": {errno}{}", Hint(hint::map_create(*.errno)))]
+    MapCreate { errno: Errno, map: String },
+    #[error("failed to update map \"{map}\": {errno}{}", Hint(hint::map_update(*.errno)))]
+    MapUpdate { errno: Errno, map: String },
+    #[error("failed to look up in map \"{map}\": {errno}")]
+    MapLookup { errno: Errno, map: String },
+    #[error("failed to delete from map \"{map}\": {errno}")]
+    MapDelete { errno: Errno, map: String },
+    #[error("map \"{map}\" has type {}, expected {expected}", map_type_name(*.actual))]
+    MapType {
+        map: String,
+        expected: &'static str,
+        actual: libbpf_sys::bpf_map_type,
+    },
+    #[error("map \"{map}\" has {kind} size {actual}, expected {expected}")]
+    MapSize {
+        map: String,
+        kind: &'static str,
+        expected: usize,
+        actual: u32,
+    },
+    #[error("map \"{inner}\" does not match the inner map of \"{map}\": {kind} is {actual}, expected {expected}")]
+    InnerMap {
+        map: String,
+        inner: String,
+        kind: &'static str,
+        expected: String,
+        actual: String,
+    },
+    #[error("failed to list loaded {kind}s: {errno}{}", Hint(hint::by_id(*.errno)))]
+    List { errno: Errno, kind: &'static str },
+    #[error("failed to open {kind} with id {id}: {errno}{}", Hint(hint::by_id(*.errno)))]
+    OpenId {
+        errno: Errno,
+        kind: &'static str,
+        id: u32,
+    },
+    #[error("failed to enable run-time stats: {errno}{}", Hint(hint::enable_stats(*.errno)))]
+    EnableStats { errno: Errno },
+    #[error("failed to test run program \"{program}\": {errno}{}", Hint(hint::test_run(*.errno)))]
+    TestRun { errno: Errno, program: String },
+    #[error("failed to pin to \"{path}\": {errno}{}", Hint(hint::pin(*.errno)))]
+    Pin { errno: Errno, path: String },
+    #[error("failed to open pinned object \"{path}\": {errno}{}", Hint(hint::open_pinned(*.errno)))]
+    OpenPinned { errno: Errno, path: String },
+    #[error("failed to open buffer on map \"{map}\": {errno}{}", Hint(hint::buffer(*.errno)))]
+    Buffer { errno: Errno, map: String },
+    #[error("failed to poll buffer: {0}")]
+    Poll(Errno),
+    #[error("failed to configure \"{name}\": {errno}{}", Hint(hint::configure(*.errno)))]
+    Configure { errno: Errno, name: String },
+    #[error("global variable \"{0}\" not found")]
+    GlobalNotFound(String),
+    #[error("global variable \"{name}\" has size {actual}, expected {expected}")]
+    GlobalSize {
+        name: String,
+        expected: usize,
+        actual: u32,
+    },
+    #[error("ifindex {ifindex} has an XDP dispatcher of unsupported version {version}")]
+    DispatcherVersion { ifindex: u32, version: u32 },
+    #[error("failed with errno {0}")]
+    Errno(Errno),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(&'static str),
+}
+
+impl Error {
+    /// The errno that caused this error, if there is one.
+    #[must_use]
+    pub fn errno(&self) -> Option<Errno> {
+        match self {
+            Error::Open { errno }
+            | Error::Load { errno, .. }
+            | Error::Attach { errno, .. }
+            | Error::TcAttach { errno, .. }
+            | Error::Detach { errno, .. }
+            | Error::MapCreate { errno, .. }
+            | Error::MapUpdate { errno, .. }
+            | Error::MapLookup { errno, .. }
+            | Error::MapDelete { errno, .. }
+            | Error::TestRun { errno, .. }
+            | Error::Pin { errno, .. }
+            | Error::OpenPinned { errno, .. }
+            | Error::List { errno, .. }
+            | Error::OpenId { errno, .. }
+            | Error::EnableStats { errno }
+            | Error::Configure { errno, .. }
+            | Error::Buffer { errno, .. }
+            | Error::Poll(errno)
+            | Error::Errno(errno) => Some(*errno),
+            Error::ProgramNotFound(_) | Error::MapNotFound(_) | Error::GlobalNotFound(_) => {
+                Some(Errno(libc::ENOENT))
+            }
+            Error::MapType { .. }
+            | Error::MapSize { .. }
+            | Error::InnerMap { .. }
+            | Error::GlobalSize { .. }
+            | Error::DispatcherVersion { .. }
+            | Error::InvalidArgument(_) => None,
+        }
+    }
+
+    /// A suggestion for how to fix common failures, if one is known.
+    #[must_use]
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Open { errno } => hint::open(*errno),
+            Error::Load { errno, .. } => hint::load(*errno),
+            Error::Attach { errno, .. } => hint::attach(*errno),
+            Error::TcAttach { errno, .. } => hint::tc_attach(*errno),
+            Error::MapCreate { errno, .. } => hint::map_create(*errno),
+            Error::MapUpdate { errno, .. } => hint::map_update(*errno),
+            Error::TestRun { errno, .. } => hint::test_run(*errno),
+            Error::Pin { errno, .. } => hint::pin(*errno),
+            Error::OpenPinned { errno, .. } => hint::open_pinned(*errno),
+            Error::List { errno, .. } | Error::OpenId { errno, .. } => hint::by_id(*errno),
+            Error::EnableStats { errno } => hint::enable_stats(*errno),
+            Error::Configure { errno, .. } => hint::configure(*errno),
+            Error::Buffer { errno, .. } => hint::buffer(*errno),
+            _ => None,
+        }
+    }
+
+    /// The verifier's explanation of why a program was rejected, if loading
+    /// failed in the verifier and the object was opened with
+    /// [ObjectBuilder::verifier_log](crate::ObjectBuilder::verifier_log).
+    #[must_use]
+    pub fn verifier_log(&self) -> Option<&str> {
+        match self {
+            Error::Load { verifier_log, .. } => verifier_log.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err.errno() {
+            Some(errno) => std::io::Error::new(std::io::Error::from(errno).kind(), err),
+            None => std::io::Error::new(std::io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
+/// An `errno` value returned by a failed syscall or libbpf call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Errno(pub i32);
+
+impl Errno {
+    /// Read the calling thread's current `errno`.
+    #[must_use]
+    pub fn last() -> Self {
+        Errno(unsafe { *libc::__errno_location() })
+    }
+
+    /// Convert the negative return code used by libbpf into an errno.
+    #[must_use]
+    pub fn from_ret(ret: i32) -> Self {
+        Errno(ret.abs())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn raw(self) -> i32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (errno {})", strerror(self.0), self.0)
+    }
+}
+
+impl From<Errno> for std::io::Error {
+    fn from(errno: Errno) -> Self {
+        std::io::Error::from_raw_os_error(errno.0)
+    }
+}
+
+impl From<i32> for Errno {
+    fn from(code: i32) -> Self {
+        Errno(code)
+    }
+}
+
+#[must_use]
+pub fn strerror(code: i32) -> String {
+    let msg_ptr = unsafe { libc::strerror(code) };
+    let msg_cstr = unsafe { std::ffi::CStr::from_ptr(msg_ptr) };
+    msg_cstr.to_string_lossy().to_string()
+}
+
+/// Name libbpf uses for a map type, such as `hash` or `xskmap`.
+#[must_use]
+pub fn map_type_name(map_type: libbpf_sys::bpf_map_type) -> String {
+    match unsafe { libbpf_sys::libbpf_bpf_map_type_str(map_type) } {
+        ptr if ptr.is_null() => format!("unknown ({map_type})"),
+        ptr => unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Name libbpf uses for a program type, such as `xdp` or `sched_cls`.
+#[must_use]
+pub fn prog_type_name(prog_type: libbpf_sys::bpf_prog_type) -> String {
+    match unsafe { libbpf_sys::libbpf_bpf_prog_type_str(prog_type) } {
+        ptr if ptr.is_null() => format!("unknown ({prog_type})"),
+        ptr => unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Name libbpf uses for an attach type, such as `xdp` or `cgroup_inet_ingress`.
+#[must_use]
+pub fn attach_type_name(attach_type: libbpf_sys::bpf_attach_type) -> String {
+    match unsafe { libbpf_sys::libbpf_bpf_attach_type_str(attach_type) } {
+        ptr if ptr.is_null() => format!("unknown ({attach_type})"),
+        ptr => unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Formats an optional hint as a suffix of an error message.
+pub struct Hint(pub Option<&'static str>);
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(hint) => write!(f, " (hint: {hint})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Suggestions for the failures people most commonly run into, keyed by the
+/// operation that failed.
+pub mod hint {
+    use super::Errno;
+
+    pub const MEMLOCK: &str =
+        "RLIMIT_MEMLOCK may be too low for the BPF maps, try raising it with `ulimit -l`";
+    pub const PRIVILEGES: &str = "loading BPF programs requires CAP_BPF or CAP_SYS_ADMIN";
+
+    /// Kernel-internal errno for operations a program type does not support,
+    /// which leaks out of the bpf syscall.
+    const ENOTSUPP: i32 = 524;
+
+    #[must_use]
+    pub fn open(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::ENOEXEC | libc::EINVAL => Some("the file is not a valid BPF ELF object"),
+            libc::ENOENT => Some("the object file or the custom BTF file does not exist"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn load(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EPERM => Some(PRIVILEGES),
+            libc::ENOMEM => Some(MEMLOCK),
+            libc::EACCES => Some("the verifier rejected the program"),
+            libc::ENOSPC => Some("the verifier log did not fit in its buffer"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn attach(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EBUSY | libc::EEXIST => {
+                Some("another XDP program is already attached to this interface")
+            }
+            libc::EOPNOTSUPP => Some("the driver does not support native XDP, try generic mode"),
+            libc::ENODEV => Some("the interface does not exist"),
+            libc::EPERM => Some(PRIVILEGES),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn tc_attach(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EEXIST => Some("a filter with this handle and priority exists, set replace"),
+            libc::EINVAL => Some("the program must be a SCHED_CLS program, from SEC(\"tc\")"),
+            libc::ENODEV => Some("the interface does not exist"),
+            libc::EPERM => Some(PRIVILEGES),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn map_create(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EINVAL => Some("the sizes, flags or inner map are not valid for the map type"),
+            libc::EPERM => Some(PRIVILEGES),
+            libc::ENOMEM => Some(MEMLOCK),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn map_update(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::E2BIG => Some("the map is full or the key is out of range"),
+            libc::EINVAL => Some(
+                "the key or value size does not match the map definition, or an inner map does \
+                 not match the template",
+            ),
+            libc::ENOMEM => Some(MEMLOCK),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn test_run(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EINVAL => {
+                Some("the context or flags are not valid, rx_queue_index needs ingress_ifindex")
+            }
+            ENOTSUPP | libc::EOPNOTSUPP => Some("the kernel cannot test run this program type"),
+            libc::EPERM => Some(PRIVILEGES),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn pin(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EPERM | libc::EINVAL => {
+                Some("the path must be on a bpffs mount, try `mount -t bpf bpf /sys/fs/bpf`")
+            }
+            libc::EEXIST => Some("something is already pinned at this path"),
+            libc::ENOENT => Some("the parent directory does not exist"),
+            libc::EACCES => Some(PRIVILEGES),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn open_pinned(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::ENOENT => Some("nothing is pinned at this path"),
+            libc::EPERM | libc::EINVAL => Some("the path must be on a bpffs mount"),
+            libc::EACCES => Some(PRIVILEGES),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn by_id(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EPERM => Some("listing and opening by id requires CAP_SYS_ADMIN"),
+            libc::ENOENT => Some("nothing is loaded with this id, it may have been unloaded"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn enable_stats(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EPERM => Some("enabling stats requires CAP_SYS_ADMIN"),
+            libc::EINVAL => Some("BPF_ENABLE_STATS requires Linux 5.8 or later"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn buffer(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EBADF | libc::ENOENT => Some("the map must be loaded before it is consumed"),
+            libc::EPERM => Some(MEMLOCK),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn configure(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EBUSY => Some("programs and maps can only be configured before loading"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errno_display_includes_message_and_code() {
+        assert_eq!(
+            "Invalid argument (errno 22)",
+            Errno(libc::EINVAL).to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_display_includes_hint() {
+        let err = Error::Load {
+            errno: Errno(libc::EPERM),
+            verifier_log: None,
+        };
+
+        assert_eq!(
+            format!(
+                "failed to load object: Operation not permitted (errno 1) (hint: {})",
+                hint::PRIVILEGES
+            ),
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_converts_to_io_error_kind() {
+        let err: std::io::Error = Error::ProgramNotFound("prog".into()).into();
+
+        assert_eq!(std::io::ErrorKind::NotFound, err.kind());
+    }
+
+    #[test]
+    fn test_pin_error_suggests_mounting_bpffs() {
+        let err = Error::Pin {
+            errno: Errno(libc::EPERM),
+            path: "/tmp/map".into(),
+        };
+
+        assert!(err.to_string().contains("mount -t bpf"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Setting global variables of an object before it is loaded. libbpf turns
+//! the `.rodata`, `.data` and `.bss` sections into array maps whose initial
+//! contents are the variables, found by name through the object's BTF.
+//!
+//! `const volatile` globals are the usual way to configure a program at load
+//! time: the verifier treats them as constants and prunes any code they rule
+//! out.
+//!
+//! ```ignore
+//! // In C: const volatile __u16 allowed_vlan = 0;
+//! let mut obj = Object::create(OBJ)?;
+//! obj.set_global("allowed_vlan", &100u16)?;
+//! obj.load()?;
+//! ```
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+use crate::map::{as_bytes, from_bytes};
+use crate::{cstr_to_string, Error, Map, MapMut, Object, Pod, Result};
+
+/// Where a variable lives inside its section's map.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct Var {
+    map: *mut libbpf_sys::bpf_map,
+    offset: usize,
+    size: usize,
+}
+
+impl Object {
+    /// Set a global variable before the object is loaded. `T` must have the
+    /// size of the variable.
+    pub fn set_global<T: Pod>(&mut self, name: &str, value: &T) -> Result<()> {
+        self.set_global_bytes(name, as_bytes(value))
+    }
+
+    /// Set a global variable from its raw bytes, which must cover the whole
+    /// variable.
+    pub fn set_global_bytes(&mut self, name: &str, value: &[u8]) -> Result<()> {
+        let var = self.find_global(name, value.len())?;
+        let mut map = MapMut {
+            map: Map::from_ptr(var.map),
+        };
+        let data = map.initial_value_mut().ok_or(Error::InvalidArgument(
+            "global variables can only be set before the object is loaded",
+        ))?;
+        data[var.offset..var.offset + var.size].copy_from_slice(value);
+        Ok(())
+    }
+
+    /// The value a global variable is loaded with. Once the object is
+    /// loaded, this reads the memory shared with the programs, so variables
+    /// in `.data` and `.bss` show their current value.
+    pub fn global<T: Pod>(&self, name: &str) -> Result<T> {
+        let var = self.find_global(name, size_of::<T>())?;
+        let mut size = 0;
+        match unsafe { libbpf_sys::bpf_map__initial_value(var.map, &mut size) } {
+            data if data.is_null() => Err(Error::GlobalNotFound(name.to_owned())),
+            data => {
+                let data = unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) };
+                Ok(from_bytes(&data[var.offset..var.offset + var.size]))
+            }
+        }
+    }
+
+    /// Find a variable in the BTF of the object's global data maps, checking
+    /// that it has `size` bytes.
+    fn find_global(&self, name: &str, size: usize) -> Result<Var> {
+        let btf = unsafe { libbpf_sys::bpf_object__btf(self.obj) };
+        if btf.is_null() {
+            return Err(Error::GlobalNotFound(name.to_owned()));
+        }
+
+        let mut map = null_mut();
+        loop {
+            map = unsafe { libbpf_sys::bpf_object__next_map(self.obj, map) };
+            if map.is_null() {
+                return Err(Error::GlobalNotFound(name.to_owned()));
+            }
+            if !unsafe { libbpf_sys::bpf_map__is_internal(map) } {
+                continue;
+            }
+            // The value type of a global data map is its section's DATASEC.
+            let datasec = unsafe { libbpf_sys::bpf_map__btf_value_type_id(map) };
+            let Some(secinfo) = (unsafe { find_var(btf, datasec, name) }) else {
+                continue;
+            };
+            if secinfo.size as usize != size {
+                return Err(Error::GlobalSize {
+                    name: name.to_owned(),
+                    expected: size,
+                    actual: secinfo.size,
+                });
+            }
+            return Ok(Var {
+                map,
+                offset: secinfo.offset as usize,
+                size,
+            });
+        }
+    }
+}
+
+impl MapMut<'_> {
+    /// Replace the initial contents of a global data map, which must be the
+    /// size of the whole section.
+    pub fn set_initial_value(&mut self, data: &[u8]) -> Result<()> {
+        let ret = unsafe {
+            libbpf_sys::bpf_map__set_initial_value(
+                self.map.map,
+                data.as_ptr() as _,
+                data.len() as _,
+            )
+        };
+        self.configure_result(ret)
+    }
+
+    /// The initial contents of a global data map, or `None` for other maps
+    /// and once the object is loaded.
+    #[must_use]
+    pub fn initial_value_mut(&mut self) -> Option<&mut [u8]> {
+        // After loading, .rodata is mapped read-only and .data and .bss are
+        // shared with the running programs.
+        if self.fd() >= 0 {
+            return None;
+        }
+        let mut size = 0;
+        match unsafe { libbpf_sys::bpf_map__initial_value(self.map.map, &mut size) } {
+            data if data.is_null() => None,
+            data => Some(unsafe { std::slice::from_raw_parts_mut(data as *mut u8, size as usize) }),
+        }
+    }
+}
+
+/// Look up a variable by name in the `DATASEC` type `datasec`.
+///
+/// # Safety
+///
+/// `btf` must be a valid BTF object.
+#[must_use]
+pub(crate) unsafe fn find_var(
+    btf: *const libbpf_sys::btf,
+    datasec: u32,
+    name: &str,
+) -> Option<libbpf_sys::btf_var_secinfo> {
+    let sec = libbpf_sys::btf__type_by_id(btf, datasec);
+    if sec.is_null() || btf_kind(&*sec) != libbpf_sys::BTF_KIND_DATASEC {
+        return None;
+    }
+    // The section's variables follow its btf_type.
+    let vars = std::slice::from_raw_parts(
+        sec.add(1) as *const libbpf_sys::btf_var_secinfo,
+        btf_vlen(&*sec),
+    );
+    vars.iter().copied().find(|secinfo| {
+        let var = libbpf_sys::btf__type_by_id(btf, secinfo.type_);
+        !var.is_null()
+            && btf_kind(&*var) == libbpf_sys::BTF_KIND_VAR
+            && cstr_to_string(libbpf_sys::btf__name_by_offset(btf, (*var).name_off)) == name
+    })
+}
+
+#[inline]
+#[must_use]
+pub(crate) fn btf_kind(t: &libbpf_sys::btf_type) -> u32 {
+    (t.info >> 24) & 0x1f
+}
+
+#[inline]
+#[must_use]
+pub(crate) fn btf_vlen(t: &libbpf_sys::btf_type) -> usize {
+    (t.info & 0xffff) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_var_in_datasec() {
+        unsafe {
+            let btf = libbpf_sys::btf__new_empty();
+            let int = libbpf_sys::btf__add_int(btf, c"int".as_ptr(), 4, 0);
+            let short = libbpf_sys::btf__add_int(btf, c"short".as_ptr(), 2, 0);
+            let a = libbpf_sys::btf__add_var(btf, c"a".as_ptr(), 1, int);
+            let b = libbpf_sys::btf__add_var(btf, c"b".as_ptr(), 1, short);
+            let sec = libbpf_sys::btf__add_datasec(btf, c".rodata".as_ptr(), 8);
+            libbpf_sys::btf__add_datasec_var_info(btf, a, 0, 4);
+            libbpf_sys::btf__add_datasec_var_info(btf, b, 4, 2);
+
+            let var = find_var(btf, sec as u32, "b").unwrap();
+            assert_eq!((4, 2), (var.offset, var.size));
+            assert!(find_var(btf, sec as u32, "c").is_none());
+            assert!(find_var(btf, int as u32, "a").is_none());
+
+            libbpf_sys::btf__free(btf);
+        }
+    }
+}
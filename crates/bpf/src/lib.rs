@@ -1,132 +1,647 @@
-use std::{ffi::CString, ptr::null};
+use std::{
+    ffi::{CStr, CString},
+    marker::PhantomData,
+    mem::size_of,
+    ops::Deref,
+    os::{
+        fd::{AsRawFd, BorrowedFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    ptr::null_mut,
+};
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub mod buffer;
+pub mod builder;
+pub mod dispatcher;
+pub mod error;
+pub mod global;
+pub mod link;
+pub mod logging;
+pub mod map;
+pub mod pin;
+pub mod query;
+pub mod skel;
+pub mod stats;
+pub mod tc;
+pub mod test_run;
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("failed with errno {0}")]
-    Errno(i32),
-    #[error("invalid argument: {0}")]
-    InvalidArgument(&'static str),
-}
+/// The raw libbpf bindings, for the `BPF_*` constants that methods such as
+/// [Map::map_type] and [Program::prog_type] use.
+pub use libbpf_sys;
+
+pub use buffer::{PerfBufConsumer, RingBufConsumer};
+pub use builder::{MapBuilder, ObjectBuilder};
+pub use dispatcher::{DispatchedProgram, RunConfig, XdpDispatcher};
+pub use error::{Errno, Error};
+pub use link::{LinkedProgram, XdpAttachOptions, XdpMode};
+pub use map::{
+    Array, ArrayOfMaps, CpuMap, CpuMapEntry, DevMap, DevMapEntry, HashMap, HashOfMaps, MapFlags,
+    PerCpuArray, PerCpuHashMap, Pod, ProgArray, XskMap,
+};
+pub use query::{MapInfo, ProgramInfo};
+pub use skel::SkeletonGenerator;
+pub use stats::{enable_stats, RunStats, StatsGuard};
+pub use tc::{TcAttachOptions, TcHook, TcLink};
+pub use test_run::{TestRunOptions, TestRunResult, XdpAction};
+
+pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Clone, Debug)]
+/// A BPF object opened with libbpf. Programs and maps found in it borrow from
+/// it, and dropping it unloads the programs and destroys the maps, except for
+/// those that are pinned, attached with a `bpf_link` or held by another fd.
+#[derive(Debug)]
 pub struct Object {
     obj: *mut libbpf_sys::bpf_object,
+    /// Set by [ObjectBuilder::verifier_log], and written to by libbpf.
+    log_buf: Option<*mut [u8]>,
 }
 
+// SAFETY: a libbpf object is not tied to the thread that opened it. Methods
+// taking `&self`, on the object and on the programs and maps borrowed from
+// it, only read libbpf's state or make syscalls on fds, so the object can be
+// shared between threads. Anything that changes it takes `&mut self`.
+unsafe impl Send for Object {}
+unsafe impl Sync for Object {}
+
 impl Object {
-    /// Create a BPF object from a buffer of a valid BPF ELF object file.
+    /// Create a BPF object from a buffer of a valid BPF ELF object file. The
+    /// buffer can be dropped once this returns, since libbpf copies everything
+    /// it needs out of it while opening. Use [ObjectBuilder] to open a file or
+    /// to set open options.
     #[must_use]
     pub fn create(obj_buf: &[u8]) -> Result<Object> {
-        match unsafe {
-            libbpf_sys::bpf_object__open_mem(obj_buf.as_ptr() as _, obj_buf.len() as u64, null())
-        } {
-            obj if obj.is_null() => Err(Error::Errno(errno())),
-            obj => Ok(Object { obj }),
+        ObjectBuilder::new().open_memory(obj_buf)
+    }
+
+    /// Open an object with `opts`, which only need to outlive the call, and
+    /// a verifier log buffer of `log_size` bytes if one is given.
+    pub(crate) fn open(
+        opts: libbpf_sys::bpf_object_open_opts,
+        log_size: Option<usize>,
+        open: impl FnOnce(&libbpf_sys::bpf_object_open_opts) -> *mut libbpf_sys::bpf_object,
+    ) -> Result<Object> {
+        logging::forward_to_log();
+
+        // libbpf only writes to the log buffer when a program fails to load,
+        // when it retries the load with logging enabled. Without a buffer it
+        // retries with its own and prints the log, which goes to `log`.
+        let log_buf = log_size.map(|size| Box::into_raw(vec![0u8; size].into_boxed_slice()));
+        let opts = match log_buf {
+            Some(log_buf) => libbpf_sys::bpf_object_open_opts {
+                kernel_log_buf: log_buf as *mut libc::c_char,
+                kernel_log_size: log_buf.len() as _,
+                kernel_log_level: 0,
+                ..opts
+            },
+            None => opts,
+        };
+        let opts = libbpf_sys::bpf_object_open_opts {
+            sz: size_of::<libbpf_sys::bpf_object_open_opts>() as _,
+            ..opts
+        };
+
+        match open(&opts) {
+            obj if obj.is_null() => {
+                let errno = Errno::last();
+                if let Some(log_buf) = log_buf {
+                    drop(unsafe { Box::from_raw(log_buf) });
+                }
+                Err(Error::Open { errno })
+            }
+            obj => Ok(Object { obj, log_buf }),
         }
     }
 
+    /// Load every program and create every map in the object. If a program is
+    /// rejected and the object was opened with [ObjectBuilder::verifier_log],
+    /// the error carries the verifier log.
     #[must_use]
-    pub fn load(&self) -> Result<()> {
+    pub fn load(&mut self) -> Result<()> {
         match unsafe { libbpf_sys::bpf_object__load(self.obj) } {
-            ret if ret < 0 => Err(Error::Errno(-ret)),
+            ret if ret < 0 => Err(Error::Load {
+                errno: Errno::from_ret(ret),
+                verifier_log: self.verifier_log(),
+            }),
             _ => Ok(()),
         }
     }
 
     #[must_use]
-    pub fn find_program(&self, name: &str) -> Result<Program> {
+    fn verifier_log(&self) -> Option<String> {
+        let log = unsafe { &*self.log_buf? };
+        let len = log.iter().position(|b| *b == 0).unwrap_or(log.len());
+        match len {
+            0 => None,
+            len => Some(String::from_utf8_lossy(&log[..len]).into_owned()),
+        }
+    }
+
+    #[must_use]
+    pub fn find_program(&self, name: &str) -> Result<Program<'_>> {
         match unsafe {
             let name = CString::new(name)
                 .map_err(|_| Error::InvalidArgument("could not convert to CString"))?;
             libbpf_sys::bpf_object__find_program_by_name(self.obj, name.as_ptr())
         } {
-            ret if ret.is_null() => Err(Error::Errno(errno())),
-            program => Ok(Program::new(program)),
+            ret if ret.is_null() => Err(Error::ProgramNotFound(name.to_owned())),
+            program => Ok(Program::from_ptr(program)),
         }
     }
 
     #[must_use]
-    pub fn find_map(&self, name: &str) -> Result<Map> {
+    pub fn find_map(&self, name: &str) -> Result<Map<'_>> {
         match unsafe {
             let name = CString::new(name)
                 .map_err(|_| Error::InvalidArgument("could not convert to CString"))?;
             libbpf_sys::bpf_object__find_map_by_name(self.obj, name.as_ptr())
         } {
-            ret if ret.is_null() => Err(Error::Errno(errno())),
-            map => Ok(Map::new(map)),
+            ret if ret.is_null() => Err(Error::MapNotFound(name.to_owned())),
+            map => Ok(Map::from_ptr(map)),
         }
     }
 
-    pub fn close(self) {
-        unsafe { libbpf_sys::bpf_object__close(self.obj) }
+    /// Find a program to configure before the object is loaded.
+    pub fn find_program_mut(&mut self, name: &str) -> Result<ProgramMut<'_>> {
+        let prog = self.find_program(name)?;
+        Ok(ProgramMut {
+            prog: Program::from_ptr(prog.prog),
+        })
+    }
+
+    /// Find a map to configure before the object is loaded.
+    pub fn find_map_mut(&mut self, name: &str) -> Result<MapMut<'_>> {
+        let map = self.find_map(name)?;
+        Ok(MapMut {
+            map: Map::from_ptr(map.map),
+        })
+    }
+
+    /// Name of the object, which defaults to the file name.
+    #[must_use]
+    pub fn name(&self) -> String {
+        unsafe { cstr_to_string(libbpf_sys::bpf_object__name(self.obj)) }
+    }
+
+    /// Every program in the object, in the order they were defined.
+    pub fn programs(&self) -> impl Iterator<Item = Program<'_>> + '_ {
+        next_programs(self.obj).map(Program::from_ptr)
+    }
+
+    /// Every program in the object, to configure before it is loaded.
+    pub fn programs_mut(&mut self) -> impl Iterator<Item = ProgramMut<'_>> + '_ {
+        next_programs(self.obj).map(|prog| ProgramMut {
+            prog: Program::from_ptr(prog),
+        })
+    }
+
+    /// Every map in the object, including the `.rodata`, `.data` and `.bss`
+    /// maps that hold global variables.
+    pub fn maps(&self) -> impl Iterator<Item = Map<'_>> + '_ {
+        next_maps(self.obj).map(Map::from_ptr)
+    }
+
+    /// Every map in the object, to configure before it is loaded.
+    pub fn maps_mut(&mut self) -> impl Iterator<Item = MapMut<'_>> + '_ {
+        next_maps(self.obj).map(|map| MapMut {
+            map: Map::from_ptr(map),
+        })
+    }
+}
+
+fn next_programs(
+    obj: *mut libbpf_sys::bpf_object,
+) -> impl Iterator<Item = *mut libbpf_sys::bpf_program> {
+    let mut prog = null_mut();
+    std::iter::from_fn(move || {
+        prog = unsafe { libbpf_sys::bpf_object__next_program(obj, prog) };
+        (!prog.is_null()).then_some(prog)
+    })
+}
+
+fn next_maps(obj: *mut libbpf_sys::bpf_object) -> impl Iterator<Item = *mut libbpf_sys::bpf_map> {
+    let mut map = null_mut();
+    std::iter::from_fn(move || {
+        map = unsafe { libbpf_sys::bpf_object__next_map(obj, map) };
+        (!map.is_null()).then_some(map)
+    })
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        unsafe {
+            libbpf_sys::bpf_object__close(self.obj);
+            if let Some(log_buf) = self.log_buf {
+                drop(Box::from_raw(log_buf));
+            }
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Program {
+/// A BPF program, either borrowed from an [Object] or opened from a file
+/// descriptor such as a bpffs pin, in which case it is `Program<'static>`.
+#[derive(Debug)]
+pub struct Program<'obj> {
     prog: *mut libbpf_sys::bpf_program,
+    handle: Option<FdHandle<libbpf_sys::bpf_prog_info>>,
+    _obj: PhantomData<&'obj Object>,
 }
 
-impl Program {
+// SAFETY: a program borrowed from an object is used like `&Object`, which is
+// `Send` and `Sync`, and one opened from an fd owns nothing else.
+unsafe impl Send for Program<'_> {}
+unsafe impl Sync for Program<'_> {}
+
+/// An open file descriptor for a program or map that does not belong to an
+/// object, with the info the kernel reported for it.
+#[derive(Debug)]
+pub(crate) struct FdHandle<I> {
+    pub(crate) fd: OwnedFd,
+    pub(crate) info: I,
+}
+
+impl<I: Default> FdHandle<I> {
+    pub(crate) fn new(fd: OwnedFd) -> std::result::Result<Self, Errno> {
+        let info = obj_get_info(fd.as_raw_fd())?;
+        Ok(FdHandle { fd, info })
+    }
+}
+
+impl<'obj> Program<'obj> {
     #[must_use]
-    pub fn new(prog: *mut libbpf_sys::bpf_program) -> Self {
-        Program { prog }
+    pub(crate) fn from_ptr(prog: *mut libbpf_sys::bpf_program) -> Self {
+        Program {
+            prog,
+            handle: None,
+            _obj: PhantomData,
+        }
+    }
+
+    /// Open another handle to the program with a duplicate of its fd. The
+    /// handle keeps the program loaded on its own, so it can outlive the
+    /// object. The object must have been loaded.
+    pub fn try_clone(&self) -> Result<Program<'static>> {
+        let fd = dup_fd(self.fd()).map_err(Error::Errno)?;
+        Program::from_fd(fd).map_err(Error::Errno)
     }
 
     #[must_use]
-    pub fn attach_xdp(&self, ifindex: u32) -> Result<LinkedProgram> {
-        match unsafe { libbpf_sys::bpf_program__attach_xdp(self.prog, ifindex as i32) } {
-            ret if ret.is_null() => Err(Error::Errno(errno())),
-            link => Ok(LinkedProgram::new(link)),
+    pub fn name(&self) -> String {
+        match &self.handle {
+            Some(handle) => unsafe { cstr_to_string(handle.info.name.as_ptr()) },
+            None => unsafe { cstr_to_string(libbpf_sys::bpf_program__name(self.prog)) },
         }
     }
-}
 
-#[derive(Clone, Debug)]
-pub struct LinkedProgram {
-    #[allow(dead_code)]
-    link: *mut libbpf_sys::bpf_link,
-}
+    /// File descriptor of the loaded program, or a negative errno if the
+    /// object has not been loaded.
+    #[inline]
+    #[must_use]
+    pub fn fd(&self) -> i32 {
+        match &self.handle {
+            Some(handle) => handle.fd.as_raw_fd(),
+            None => unsafe { libbpf_sys::bpf_program__fd(self.prog) },
+        }
+    }
+
+    /// The `BPF_PROG_TYPE_*` of the program.
+    #[must_use]
+    pub fn prog_type(&self) -> libbpf_sys::bpf_prog_type {
+        match &self.handle {
+            Some(handle) => handle.info.type_,
+            None => unsafe { libbpf_sys::bpf_program__type(self.prog) },
+        }
+    }
+
+    /// ELF section the program was defined in, such as `xdp` or
+    /// `tc/ingress`. Only known for programs of an object.
+    #[must_use]
+    pub fn section_name(&self) -> Option<String> {
+        match &self.handle {
+            Some(_) => None,
+            None => {
+                Some(unsafe { cstr_to_string(libbpf_sys::bpf_program__section_name(self.prog)) })
+            }
+        }
+    }
+
+    /// The `BPF_*` attach type the program is loaded for. Only known for
+    /// programs of an object.
+    #[must_use]
+    pub fn expected_attach_type(&self) -> Option<libbpf_sys::bpf_attach_type> {
+        match &self.handle {
+            Some(_) => None,
+            None => Some(unsafe { libbpf_sys::bpf_program__expected_attach_type(self.prog) }),
+        }
+    }
 
-impl LinkedProgram {
+    /// Whether the program is loaded along with its object. Programs opened
+    /// from an fd are always loaded.
     #[must_use]
-    pub fn new(link: *mut libbpf_sys::bpf_link) -> Self {
-        LinkedProgram { link }
+    pub fn autoload(&self) -> bool {
+        match &self.handle {
+            Some(_) => true,
+            None => unsafe { libbpf_sys::bpf_program__autoload(self.prog) },
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Map {
+impl Program<'static> {
+    pub(crate) fn from_fd(fd: OwnedFd) -> std::result::Result<Self, Errno> {
+        Ok(Program {
+            prog: std::ptr::null_mut(),
+            handle: Some(FdHandle::new(fd)?),
+            _obj: PhantomData,
+        })
+    }
+}
+
+/// A BPF map, either borrowed from an [Object] or opened from a file
+/// descriptor such as a bpffs pin, in which case it is `Map<'static>`.
+#[derive(Debug)]
+pub struct Map<'obj> {
     map: *mut libbpf_sys::bpf_map,
+    handle: Option<FdHandle<libbpf_sys::bpf_map_info>>,
+    _obj: PhantomData<&'obj Object>,
 }
 
-impl Map {
+// SAFETY: as for Program.
+unsafe impl Send for Map<'_> {}
+unsafe impl Sync for Map<'_> {}
+
+impl<'obj> Map<'obj> {
+    #[must_use]
+    pub(crate) fn from_ptr(map: *mut libbpf_sys::bpf_map) -> Self {
+        Map {
+            map,
+            handle: None,
+            _obj: PhantomData,
+        }
+    }
+
+    /// Open another handle to the map with a duplicate of its fd. The handle
+    /// keeps the map alive on its own, so it can outlive the object. The
+    /// object must have been loaded.
+    pub fn try_clone(&self) -> Result<Map<'static>> {
+        let fd = dup_fd(self.fd()).map_err(Error::Errno)?;
+        Map::from_fd(fd).map_err(Error::Errno)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> String {
+        match &self.handle {
+            Some(handle) => unsafe { cstr_to_string(handle.info.name.as_ptr()) },
+            None => unsafe { cstr_to_string(libbpf_sys::bpf_map__name(self.map)) },
+        }
+    }
+
+    /// File descriptor of the map, or a negative errno if the object has not
+    /// been loaded.
+    #[inline]
+    #[must_use]
+    pub fn fd(&self) -> i32 {
+        match &self.handle {
+            Some(handle) => handle.fd.as_raw_fd(),
+            None => unsafe { libbpf_sys::bpf_map__fd(self.map) },
+        }
+    }
+
+    #[must_use]
+    pub fn key_size(&self) -> u32 {
+        match &self.handle {
+            Some(handle) => handle.info.key_size,
+            None => unsafe { libbpf_sys::bpf_map__key_size(self.map) },
+        }
+    }
+
+    #[must_use]
+    pub fn value_size(&self) -> u32 {
+        match &self.handle {
+            Some(handle) => handle.info.value_size,
+            None => unsafe { libbpf_sys::bpf_map__value_size(self.map) },
+        }
+    }
+
+    /// The `BPF_MAP_TYPE_*` of the map.
+    #[must_use]
+    pub fn map_type(&self) -> libbpf_sys::bpf_map_type {
+        match &self.handle {
+            Some(handle) => handle.info.type_,
+            None => unsafe { libbpf_sys::bpf_map__type(self.map) },
+        }
+    }
+
     #[must_use]
-    pub fn new(map: *mut libbpf_sys::bpf_map) -> Self {
-        Map { map }
+    pub fn max_entries(&self) -> u32 {
+        match &self.handle {
+            Some(handle) => handle.info.max_entries,
+            None => unsafe { libbpf_sys::bpf_map__max_entries(self.map) },
+        }
+    }
+
+    /// The `BPF_F_*` flags the map was created with.
+    #[must_use]
+    pub fn map_flags(&self) -> u32 {
+        match &self.handle {
+            Some(handle) => handle.info.map_flags,
+            None => unsafe { libbpf_sys::bpf_map__map_flags(self.map) },
+        }
     }
 
     #[must_use]
     pub fn update(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        match unsafe {
-            libbpf_sys::bpf_map__update_elem(
-                self.map,
-                key.as_ptr() as _,
-                key.len() as u64,
-                value.as_ptr() as _,
-                value.len() as u64,
-                0,
-            )
-        } {
-            ret if ret < 0 => Err(Error::Errno(errno())),
+        let ret = match &self.handle {
+            // The kernel reads key_size and value_size bytes regardless of the
+            // buffers, so they are checked here as libbpf does for object maps.
+            Some(_)
+                if key.len() != self.key_size() as usize
+                    || value.len() != self.value_size() as usize =>
+            {
+                -libc::EINVAL
+            }
+            Some(handle) => unsafe {
+                libbpf_sys::bpf_map_update_elem(
+                    handle.fd.as_raw_fd(),
+                    key.as_ptr() as _,
+                    value.as_ptr() as _,
+                    0,
+                )
+            },
+            None => unsafe {
+                libbpf_sys::bpf_map__update_elem(
+                    self.map,
+                    key.as_ptr() as _,
+                    key.len() as u64,
+                    value.as_ptr() as _,
+                    value.len() as u64,
+                    0,
+                )
+            },
+        };
+
+        match ret {
+            ret if ret < 0 => Err(Error::MapUpdate {
+                errno: Errno::from_ret(ret),
+                map: self.name(),
+            }),
             _ => Ok(()),
         }
     }
 }
 
-#[must_use]
-pub(crate) fn errno() -> i32 {
-    unsafe { *libc::__errno_location() }
+impl Map<'static> {
+    pub(crate) fn from_fd(fd: OwnedFd) -> std::result::Result<Self, Errno> {
+        Ok(Map {
+            map: std::ptr::null_mut(),
+            handle: Some(FdHandle::new(fd)?),
+            _obj: PhantomData,
+        })
+    }
+}
+
+/// A program of an object that has not been loaded yet, borrowed mutably so
+/// it can be configured. It derefs to [Program].
+#[derive(Debug)]
+pub struct ProgramMut<'obj> {
+    prog: Program<'obj>,
+}
+
+impl<'obj> Deref for ProgramMut<'obj> {
+    type Target = Program<'obj>;
+
+    fn deref(&self) -> &Program<'obj> {
+        &self.prog
+    }
+}
+
+impl ProgramMut<'_> {
+    /// Override the program type that libbpf derived from the section name.
+    pub fn set_prog_type(&mut self, prog_type: libbpf_sys::bpf_prog_type) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_program__set_type(self.prog.prog, prog_type) };
+        self.configure_result(ret)
+    }
+
+    pub fn set_expected_attach_type(
+        &mut self,
+        attach_type: libbpf_sys::bpf_attach_type,
+    ) -> Result<()> {
+        let ret = unsafe {
+            libbpf_sys::bpf_program__set_expected_attach_type(self.prog.prog, attach_type)
+        };
+        self.configure_result(ret)
+    }
+
+    /// Set the function a `BPF_PROG_TYPE_EXT` or tracing program attaches to,
+    /// in the loaded program `target_fd`, or in the kernel if it is 0.
+    pub fn set_attach_target(&mut self, target_fd: i32, func_name: &str) -> Result<()> {
+        let func_name = CString::new(func_name)
+            .map_err(|_| Error::InvalidArgument("could not convert to CString"))?;
+        let ret = unsafe {
+            libbpf_sys::bpf_program__set_attach_target(
+                self.prog.prog,
+                target_fd,
+                func_name.as_ptr(),
+            )
+        };
+        self.configure_result(ret)
+    }
+
+    /// Choose whether the program is loaded with its object, for example to
+    /// skip programs the running kernel cannot verify.
+    pub fn set_autoload(&mut self, autoload: bool) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_program__set_autoload(self.prog.prog, autoload) };
+        self.configure_result(ret)
+    }
+
+    fn configure_result(&self, ret: i32) -> Result<()> {
+        configure_result(ret, || self.name())
+    }
+}
+
+/// A map of an object that has not been loaded yet, borrowed mutably so it
+/// can be configured. It derefs to [Map].
+#[derive(Debug)]
+pub struct MapMut<'obj> {
+    map: Map<'obj>,
+}
+
+impl<'obj> Deref for MapMut<'obj> {
+    type Target = Map<'obj>;
+
+    fn deref(&self) -> &Map<'obj> {
+        &self.map
+    }
+}
+
+impl MapMut<'_> {
+    /// Resize the map, for example to make an `XSKMAP` as large as the number
+    /// of queues on the interface.
+    pub fn set_max_entries(&mut self, max_entries: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_max_entries(self.map.map, max_entries) };
+        self.configure_result(ret)
+    }
+
+    /// Override the map type declared in the object, such as to fall back
+    /// from `LRU_HASH` to `HASH`.
+    pub fn set_map_type(&mut self, map_type: libbpf_sys::bpf_map_type) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_type(self.map.map, map_type) };
+        self.configure_result(ret)
+    }
+
+    /// Use `inner` as the template for the maps stored in this
+    /// `ARRAY_OF_MAPS` or `HASH_OF_MAPS`, instead of the one declared with
+    /// `__array(values, ...)`. libbpf doesn't duplicate the fd, so `inner`
+    /// must stay open until the object is loaded.
+    pub fn set_inner_map(&mut self, inner: &Map) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_inner_map_fd(self.map.map, inner.fd()) };
+        self.configure_result(ret)
+    }
+
+    pub(crate) fn configure_result(&self, ret: i32) -> Result<()> {
+        configure_result(ret, || self.name())
+    }
+}
+
+fn configure_result(ret: i32, name: impl FnOnce() -> String) -> Result<()> {
+    match ret {
+        ret if ret < 0 => Err(Error::Configure {
+            errno: Errno::from_ret(ret),
+            name: name(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Duplicate a borrowed fd, so the copy can be closed independently.
+pub(crate) fn dup_fd(fd: i32) -> std::result::Result<OwnedFd, Errno> {
+    if fd < 0 {
+        return Err(Errno::from_ret(fd));
+    }
+    unsafe { BorrowedFd::borrow_raw(fd) }
+        .try_clone_to_owned()
+        .map_err(|err| Errno(err.raw_os_error().unwrap_or(libc::EIO)))
+}
+
+/// Query the kernel's `bpf_*_info` struct for a program, map or link fd.
+pub(crate) fn obj_get_info<I: Default>(fd: i32) -> std::result::Result<I, Errno> {
+    let mut info = I::default();
+    let mut len = size_of::<I>() as u32;
+    match unsafe { libbpf_sys::bpf_obj_get_info_by_fd(fd, &mut info as *mut I as *mut _, &mut len) }
+    {
+        ret if ret < 0 => Err(Errno::from_ret(ret)),
+        _ => Ok(info),
+    }
+}
+
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::InvalidArgument("could not convert to CString"))
+}
+
+/// Copy a C string owned by libbpf, tolerating null pointers.
+pub(crate) unsafe fn cstr_to_string(ptr: *const libc::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
 }
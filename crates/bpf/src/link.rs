@@ -0,0 +1,314 @@
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr::null;
+
+use crate::{dup_fd, Errno, Error, Program, Result};
+
+/// Which XDP hook the program is attached to.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum XdpMode {
+    /// Let the kernel use the native hook if the driver has one, and the
+    /// generic hook otherwise.
+    #[default]
+    Auto,
+    /// Generic (`XDP_FLAGS_SKB_MODE`) hook, which works on any interface but
+    /// runs after the socket buffer has been allocated. Useful on veth.
+    Generic,
+    /// Native (`XDP_FLAGS_DRV_MODE`) hook in the driver.
+    Native,
+    /// Offloaded (`XDP_FLAGS_HW_MODE`) to the NIC.
+    Hardware,
+}
+
+impl XdpMode {
+    #[must_use]
+    pub(crate) fn flags(self) -> u32 {
+        match self {
+            XdpMode::Auto => 0,
+            XdpMode::Generic => libbpf_sys::XDP_FLAGS_SKB_MODE,
+            XdpMode::Native => libbpf_sys::XDP_FLAGS_DRV_MODE,
+            XdpMode::Hardware => libbpf_sys::XDP_FLAGS_HW_MODE,
+        }
+    }
+}
+
+/// Options for attaching an XDP program through netlink, which unlike a
+/// `bpf_link` lets the caller choose the mode.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct XdpAttachOptions {
+    mode: XdpMode,
+    update_if_noexist: bool,
+    replace: Option<i32>,
+}
+
+impl XdpAttachOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn mode(mut self, mode: XdpMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Fail with `EBUSY` instead of replacing a program that is already
+    /// attached (`XDP_FLAGS_UPDATE_IF_NOEXIST`).
+    #[must_use]
+    pub fn update_if_noexist(mut self, update_if_noexist: bool) -> Self {
+        self.update_if_noexist = update_if_noexist;
+        self
+    }
+
+    /// Atomically replace the program with the given fd, failing if a
+    /// different program is attached (`XDP_FLAGS_REPLACE`).
+    #[must_use]
+    pub fn replace(mut self, old_prog_fd: i32) -> Self {
+        self.replace = Some(old_prog_fd);
+        self
+    }
+
+    #[must_use]
+    pub fn flags(&self) -> u32 {
+        let mut flags = self.mode.flags();
+        if self.update_if_noexist {
+            flags |= libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST;
+        }
+        if self.replace.is_some() {
+            flags |= libbpf_sys::XDP_FLAGS_REPLACE;
+        }
+        flags
+    }
+}
+
+/// A program attached to an XDP hook. It is detached when dropped, or
+/// explicitly with [LinkedProgram::detach] to observe errors. A `bpf_link`
+/// that has been pinned stays attached after it is dropped, until the pin is
+/// removed. The attachment keeps the program loaded, so it does not borrow
+/// from the program's object.
+#[derive(Debug)]
+#[must_use = "the program is detached when the link is dropped"]
+pub struct LinkedProgram {
+    ifindex: u32,
+    attachment: Attachment,
+}
+
+#[derive(Debug)]
+enum Attachment {
+    Link(OwnedFd),
+    /// Holds its own fd of the attached program, which is needed to detach
+    /// it only if it is still the one attached.
+    Netlink {
+        prog: OwnedFd,
+        flags: u32,
+    },
+    Detached,
+}
+
+impl LinkedProgram {
+    pub(crate) fn from_link_fd(fd: OwnedFd, ifindex: u32) -> Self {
+        LinkedProgram {
+            ifindex,
+            attachment: Attachment::Link(fd),
+        }
+    }
+
+    #[must_use]
+    pub fn ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    /// File descriptor of the `bpf_link`, if the program was attached with one.
+    #[must_use]
+    pub fn link_fd(&self) -> Option<i32> {
+        match &self.attachment {
+            Attachment::Link(fd) => Some(fd.as_raw_fd()),
+            _ => None,
+        }
+    }
+
+    /// Atomically swap the attached program for another one.
+    pub fn update(&mut self, prog: &Program) -> Result<()> {
+        let ret = match &mut self.attachment {
+            Attachment::Link(fd) => unsafe {
+                libbpf_sys::bpf_link_update(fd.as_raw_fd(), prog.fd(), null())
+            },
+            Attachment::Netlink { prog: old, flags } => match dup_fd(prog.fd()) {
+                Ok(new) => {
+                    let ret = xdp_attach(
+                        self.ifindex,
+                        new.as_raw_fd(),
+                        update_flags(*flags),
+                        Some(old.as_raw_fd()),
+                    );
+                    if ret == 0 {
+                        *old = new;
+                    }
+                    ret
+                }
+                Err(errno) => -errno.0,
+            },
+            Attachment::Detached => -libc::ENOENT,
+        };
+
+        match ret {
+            ret if ret < 0 => Err(Error::Attach {
+                errno: Errno::from_ret(ret),
+                program: prog.name(),
+                ifindex: self.ifindex,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Detach the program from the interface, even if the link is pinned. A
+    /// netlink attachment is only removed if it is still this program, so a
+    /// program that replaced it in the meantime is left alone.
+    pub fn detach(mut self) -> Result<()> {
+        let ret = match std::mem::replace(&mut self.attachment, Attachment::Detached) {
+            Attachment::Link(fd) => unsafe { libbpf_sys::bpf_link_detach(fd.as_raw_fd()) },
+            Attachment::Netlink { prog, flags } => {
+                xdp_detach(self.ifindex, prog.as_raw_fd(), flags)
+            }
+            Attachment::Detached => 0,
+        };
+
+        match ret {
+            ret if ret < 0 => Err(Error::Detach {
+                errno: Errno::from_ret(ret),
+                ifindex: self.ifindex,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for LinkedProgram {
+    fn drop(&mut self) {
+        // Closing a link fd detaches it unless it is pinned.
+        if let Attachment::Netlink { prog, flags } = &self.attachment {
+            xdp_detach(self.ifindex, prog.as_raw_fd(), *flags);
+        }
+    }
+}
+
+impl Program<'_> {
+    /// Attach the program with a `bpf_link`, letting the kernel pick the mode.
+    /// Fails if any program is already attached to the interface.
+    pub fn attach_xdp(&self, ifindex: u32) -> Result<LinkedProgram> {
+        let ret = unsafe {
+            libbpf_sys::bpf_link_create(self.fd(), ifindex as i32, libbpf_sys::BPF_XDP, null())
+        };
+        match ret {
+            ret if ret < 0 => Err(Error::Attach {
+                errno: Errno::from_ret(ret),
+                program: self.name(),
+                ifindex,
+            }),
+            fd => Ok(LinkedProgram::from_link_fd(
+                unsafe { OwnedFd::from_raw_fd(fd) },
+                ifindex,
+            )),
+        }
+    }
+
+    /// Attach the program through netlink, with control over the mode and over
+    /// what happens to an existing program.
+    pub fn attach_xdp_with(&self, ifindex: u32, opts: XdpAttachOptions) -> Result<LinkedProgram> {
+        let attach_error = |errno| Error::Attach {
+            errno,
+            program: self.name(),
+            ifindex,
+        };
+        let prog = dup_fd(self.fd()).map_err(attach_error)?;
+        let flags = opts.flags();
+        match xdp_attach(ifindex, prog.as_raw_fd(), flags, opts.replace) {
+            ret if ret < 0 => Err(attach_error(Errno::from_ret(ret))),
+            _ => Ok(LinkedProgram {
+                ifindex,
+                attachment: Attachment::Netlink {
+                    prog,
+                    flags: stored_flags(flags),
+                },
+            }),
+        }
+    }
+}
+
+/// The flags kept for later updates and detaching. Whether the first attach
+/// could replace a program doesn't apply to them, and with
+/// `XDP_FLAGS_UPDATE_IF_NOEXIST` every update would fail with `EBUSY`.
+#[must_use]
+fn stored_flags(flags: u32) -> u32 {
+    flags & !(libbpf_sys::XDP_FLAGS_REPLACE | libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST)
+}
+
+/// The flags to replace the attached program with, given the stored flags.
+#[must_use]
+fn update_flags(stored: u32) -> u32 {
+    stored | libbpf_sys::XDP_FLAGS_REPLACE
+}
+
+pub(crate) fn xdp_attach(ifindex: u32, prog_fd: i32, flags: u32, old_prog_fd: Option<i32>) -> i32 {
+    let opts = attach_opts(old_prog_fd);
+    unsafe { libbpf_sys::bpf_xdp_attach(ifindex as i32, prog_fd, flags, &opts) }
+}
+
+pub(crate) fn xdp_detach(ifindex: u32, prog_fd: i32, flags: u32) -> i32 {
+    let opts = attach_opts(Some(prog_fd));
+    let flags = (flags & libbpf_sys::XDP_FLAGS_MODES) | libbpf_sys::XDP_FLAGS_REPLACE;
+    unsafe { libbpf_sys::bpf_xdp_detach(ifindex as i32, flags, &opts) }
+}
+
+#[must_use]
+fn attach_opts(old_prog_fd: Option<i32>) -> libbpf_sys::bpf_xdp_attach_opts {
+    libbpf_sys::bpf_xdp_attach_opts {
+        sz: size_of::<libbpf_sys::bpf_xdp_attach_opts>() as _,
+        old_prog_fd: old_prog_fd.unwrap_or(0),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_flags() {
+        let opts = XdpAttachOptions::new()
+            .mode(XdpMode::Generic)
+            .update_if_noexist(true);
+
+        assert_eq!(
+            libbpf_sys::XDP_FLAGS_SKB_MODE | libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST,
+            opts.flags()
+        );
+    }
+
+    #[test]
+    fn test_replace_sets_replace_flag() {
+        let opts = XdpAttachOptions::new().mode(XdpMode::Native).replace(7);
+
+        assert_eq!(
+            libbpf_sys::XDP_FLAGS_DRV_MODE | libbpf_sys::XDP_FLAGS_REPLACE,
+            opts.flags()
+        );
+    }
+
+    #[test]
+    fn test_update_drops_update_if_noexist() {
+        let opts = XdpAttachOptions::new()
+            .mode(XdpMode::Generic)
+            .update_if_noexist(true)
+            .replace(7);
+
+        let stored = stored_flags(opts.flags());
+
+        assert_eq!(libbpf_sys::XDP_FLAGS_SKB_MODE, stored);
+        assert_eq!(
+            libbpf_sys::XDP_FLAGS_SKB_MODE | libbpf_sys::XDP_FLAGS_REPLACE,
+            update_flags(stored)
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Forwarding libbpf's messages to the `log` crate. libbpf prints to stderr by
+//! default; once [forward_to_log] has run, its warnings, info and debug
+//! messages are logged at the matching level with the `libbpf` target, where
+//! they can be filtered like any other.
+//!
+//! Opening an [Object](crate::Object) installs the callback, so calling
+//! [forward_to_log] directly is only needed to capture messages from before
+//! then.
+use std::ffi::CStr;
+use std::sync::Once;
+
+/// Target that libbpf messages are logged with.
+pub const TARGET: &str = "libbpf";
+
+extern "C" {
+    // The print callback receives a va_list, which Rust cannot format.
+    fn vasprintf(
+        strp: *mut *mut libc::c_char,
+        fmt: *const libc::c_char,
+        ap: *mut libbpf_sys::__va_list_tag,
+    ) -> libc::c_int;
+}
+
+/// Install a libbpf print callback that forwards to the `log` crate. Only the
+/// first call has an effect.
+pub fn forward_to_log() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        libbpf_sys::libbpf_set_print(Some(print));
+    });
+}
+
+#[must_use]
+fn level(level: libbpf_sys::libbpf_print_level) -> log::Level {
+    match level {
+        libbpf_sys::LIBBPF_WARN => log::Level::Warn,
+        libbpf_sys::LIBBPF_INFO => log::Level::Info,
+        _ => log::Level::Debug,
+    }
+}
+
+unsafe extern "C" fn print(
+    print_level: libbpf_sys::libbpf_print_level,
+    fmt: *const libc::c_char,
+    ap: *mut libbpf_sys::__va_list_tag,
+) -> libc::c_int {
+    let level = level(print_level);
+    if !log::log_enabled!(target: TARGET, level) {
+        return 0;
+    }
+
+    let mut msg = std::ptr::null_mut();
+    let len = vasprintf(&mut msg, fmt, ap);
+    if len < 0 {
+        return len;
+    }
+    let text = CStr::from_ptr(msg).to_string_lossy();
+    let text = text.trim_end();
+    log::log!(target: TARGET, level, "{}", text.strip_prefix("libbpf: ").unwrap_or(text));
+    libc::free(msg as _);
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_map_to_log_levels() {
+        assert_eq!(log::Level::Warn, level(libbpf_sys::LIBBPF_WARN));
+        assert_eq!(log::Level::Info, level(libbpf_sys::LIBBPF_INFO));
+        assert_eq!(log::Level::Debug, level(libbpf_sys::LIBBPF_DEBUG));
+    }
+}
@@ -0,0 +1,1452 @@
+//! Typed wrappers over BPF maps. Each wrapper checks the map type and the key
+//! and value sizes when it is created, so every operation after that copies
+//! plain Rust values in and out of the map.
+//!
+//! ```ignore
+//! let counters: HashMap<u32, u64> = HashMap::new(obj.find_map("counters")?)?;
+//! counters.update(&6, &0, MapFlags::NO_EXIST)?;
+//! for (proto, count) in counters.iter() {
+//!     println!("{proto}: {count}");
+//! }
+//!
+//! let stats: PerCpuArray<u64> = PerCpuArray::new(obj.find_map("stats")?)?;
+//! let total: u64 = stats.lookup(0)?.iter().sum();
+//! ```
+use std::marker::PhantomData;
+use std::mem::{size_of, size_of_val};
+use std::ops::BitOr;
+use std::ptr::null_mut;
+
+use crate::error::map_type_name;
+use crate::{Errno, Error, Map, Object, Program, Result};
+
+/// Plain data that can be copied to and from map memory byte for byte.
+///
+/// # Safety
+///
+/// Implementors must have no padding and no pointers, and every bit pattern
+/// must be a valid value. Structs shared with BPF programs should be
+/// `#[repr(C)]` with explicit padding fields.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Flags for map updates.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct MapFlags(u64);
+
+impl MapFlags {
+    /// Create the entry, or overwrite it if it exists.
+    pub const ANY: MapFlags = MapFlags(libbpf_sys::BPF_ANY as u64);
+    /// Only create the entry, failing with `EEXIST` if it exists.
+    pub const NO_EXIST: MapFlags = MapFlags(libbpf_sys::BPF_NOEXIST as u64);
+    /// Only overwrite the entry, failing with `ENOENT` if it does not exist.
+    pub const EXIST: MapFlags = MapFlags(libbpf_sys::BPF_EXIST as u64);
+    /// Hold the value's `bpf_spin_lock` while copying it.
+    pub const LOCK: MapFlags = MapFlags(libbpf_sys::BPF_F_LOCK as u64);
+
+    #[inline]
+    #[must_use]
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+}
+
+impl BitOr for MapFlags {
+    type Output = MapFlags;
+
+    fn bitor(self, rhs: MapFlags) -> MapFlags {
+        MapFlags(self.0 | rhs.0)
+    }
+}
+
+/// Number of possible CPUs, which is how many values a per-CPU map holds for
+/// each key.
+pub fn num_possible_cpus() -> Result<usize> {
+    match unsafe { libbpf_sys::libbpf_num_possible_cpus() } {
+        ret if ret < 0 => Err(Error::Errno(Errno::from_ret(ret))),
+        cpus => Ok(cpus as usize),
+    }
+}
+
+/// A hash map (`BPF_MAP_TYPE_HASH` or `BPF_MAP_TYPE_LRU_HASH`).
+#[derive(Debug)]
+pub struct HashMap<'obj, K, V> {
+    raw: RawMap<'obj>,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<'obj, K: Pod, V: Pod> HashMap<'obj, K, V> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[
+                libbpf_sys::BPF_MAP_TYPE_HASH,
+                libbpf_sys::BPF_MAP_TYPE_LRU_HASH,
+            ],
+            "hash or lru_hash",
+            size_of::<K>(),
+            size_of::<V>(),
+        )?;
+        Ok(HashMap {
+            raw,
+            _types: PhantomData,
+        })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    pub fn lookup(&self, key: &K) -> Result<Option<V>> {
+        let mut value = vec![0; self.raw.value_size];
+        match self.raw.lookup(as_bytes(key), &mut value)? {
+            true => Ok(Some(from_bytes(&value))),
+            false => Ok(None),
+        }
+    }
+
+    pub fn update(&self, key: &K, value: &V, flags: MapFlags) -> Result<()> {
+        self.raw.update(as_bytes(key), as_bytes(value), flags)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<()> {
+        self.raw.delete(as_bytes(key))
+    }
+
+    /// Iterate over the keys. Entries added or removed concurrently may be
+    /// missed.
+    #[must_use]
+    pub fn keys(&self) -> Keys<'_, K> {
+        Keys::new(&self.raw)
+    }
+
+    /// Iterate over the entries, skipping any removed while iterating.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.keys()
+            .filter_map(|key| Some((key, self.lookup(&key).ok()??)))
+    }
+
+    /// Read every entry with as few syscalls as possible.
+    pub fn lookup_batch(&self) -> Result<Vec<(K, V)>> {
+        let (keys, values) = self.raw.lookup_batch()?;
+        Ok(zip_entries(&keys, &values, self.raw.value_size, from_bytes))
+    }
+
+    pub fn update_batch(&self, keys: &[K], values: &[V], flags: MapFlags) -> Result<()> {
+        if keys.len() != values.len() {
+            return Err(Error::InvalidArgument(
+                "batch must have as many values as keys",
+            ));
+        }
+        self.raw
+            .update_batch(slice_as_bytes(keys), slice_as_bytes(values), flags)
+    }
+
+    pub fn delete_batch(&self, keys: &[K]) -> Result<()> {
+        self.raw.delete_batch(slice_as_bytes(keys))
+    }
+}
+
+/// A per-CPU hash map (`BPF_MAP_TYPE_PERCPU_HASH` or
+/// `BPF_MAP_TYPE_LRU_PERCPU_HASH`), holding one value per possible CPU for
+/// each key.
+#[derive(Debug)]
+pub struct PerCpuHashMap<'obj, K, V> {
+    raw: RawMap<'obj>,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<'obj, K: Pod, V: Pod> PerCpuHashMap<'obj, K, V> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[
+                libbpf_sys::BPF_MAP_TYPE_PERCPU_HASH,
+                libbpf_sys::BPF_MAP_TYPE_LRU_PERCPU_HASH,
+            ],
+            "percpu_hash or lru_percpu_hash",
+            size_of::<K>(),
+            size_of::<V>(),
+        )?
+        .per_cpu()?;
+        Ok(PerCpuHashMap {
+            raw,
+            _types: PhantomData,
+        })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    pub fn lookup(&self, key: &K) -> Result<Option<Vec<V>>> {
+        let mut values = vec![0; self.raw.value_size];
+        match self.raw.lookup(as_bytes(key), &mut values)? {
+            true => Ok(Some(from_per_cpu_bytes(&values))),
+            false => Ok(None),
+        }
+    }
+
+    /// Set the value on every CPU, so `values` must have one entry per
+    /// possible CPU.
+    pub fn update(&self, key: &K, values: &[V], flags: MapFlags) -> Result<()> {
+        let values = self.raw.per_cpu_values(values)?;
+        self.raw.update(as_bytes(key), &values, flags)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<()> {
+        self.raw.delete(as_bytes(key))
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> Keys<'_, K> {
+        Keys::new(&self.raw)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, Vec<V>)> + '_ {
+        self.keys()
+            .filter_map(|key| Some((key, self.lookup(&key).ok()??)))
+    }
+
+    pub fn lookup_batch(&self) -> Result<Vec<(K, Vec<V>)>> {
+        let (keys, values) = self.raw.lookup_batch()?;
+        Ok(zip_entries(
+            &keys,
+            &values,
+            self.raw.value_size,
+            from_per_cpu_bytes,
+        ))
+    }
+
+    pub fn delete_batch(&self, keys: &[K]) -> Result<()> {
+        self.raw.delete_batch(slice_as_bytes(keys))
+    }
+}
+
+/// An array (`BPF_MAP_TYPE_ARRAY`) indexed from zero to `len() - 1`. Every
+/// index always holds a value, so entries can be overwritten but not deleted.
+#[derive(Debug)]
+pub struct Array<'obj, V> {
+    raw: RawMap<'obj>,
+    _types: PhantomData<V>,
+}
+
+impl<'obj, V: Pod> Array<'obj, V> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_ARRAY],
+            "array",
+            size_of::<u32>(),
+            size_of::<V>(),
+        )?;
+        Ok(Array {
+            raw,
+            _types: PhantomData,
+        })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> u32 {
+        self.raw.map.max_entries()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn lookup(&self, index: u32) -> Result<V> {
+        let mut value = vec![0; self.raw.value_size];
+        self.raw.lookup_index(index, &mut value)?;
+        Ok(from_bytes(&value))
+    }
+
+    pub fn update(&self, index: u32, value: &V, flags: MapFlags) -> Result<()> {
+        self.raw.update(as_bytes(&index), as_bytes(value), flags)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = V> + '_ {
+        (0..self.len()).map_while(|index| self.lookup(index).ok())
+    }
+
+    pub fn lookup_batch(&self) -> Result<Vec<(u32, V)>> {
+        let (keys, values) = self.raw.lookup_batch()?;
+        Ok(zip_entries(&keys, &values, self.raw.value_size, from_bytes))
+    }
+
+    pub fn update_batch(&self, indices: &[u32], values: &[V], flags: MapFlags) -> Result<()> {
+        if indices.len() != values.len() {
+            return Err(Error::InvalidArgument(
+                "batch must have as many values as keys",
+            ));
+        }
+        self.raw
+            .update_batch(slice_as_bytes(indices), slice_as_bytes(values), flags)
+    }
+}
+
+/// A per-CPU array (`BPF_MAP_TYPE_PERCPU_ARRAY`), holding one value per
+/// possible CPU at each index.
+#[derive(Debug)]
+pub struct PerCpuArray<'obj, V> {
+    raw: RawMap<'obj>,
+    _types: PhantomData<V>,
+}
+
+impl<'obj, V: Pod> PerCpuArray<'obj, V> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_PERCPU_ARRAY],
+            "percpu_array",
+            size_of::<u32>(),
+            size_of::<V>(),
+        )?
+        .per_cpu()?;
+        Ok(PerCpuArray {
+            raw,
+            _types: PhantomData,
+        })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> u32 {
+        self.raw.map.max_entries()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn lookup(&self, index: u32) -> Result<Vec<V>> {
+        let mut values = vec![0; self.raw.value_size];
+        self.raw.lookup_index(index, &mut values)?;
+        Ok(from_per_cpu_bytes(&values))
+    }
+
+    /// Set the value on every CPU, so `values` must have one entry per
+    /// possible CPU.
+    pub fn update(&self, index: u32, values: &[V], flags: MapFlags) -> Result<()> {
+        let values = self.raw.per_cpu_values(values)?;
+        self.raw.update(as_bytes(&index), &values, flags)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<V>> + '_ {
+        (0..self.len()).map_while(|index| self.lookup(index).ok())
+    }
+
+    pub fn lookup_batch(&self) -> Result<Vec<(u32, Vec<V>)>> {
+        let (keys, values) = self.raw.lookup_batch()?;
+        Ok(zip_entries(
+            &keys,
+            &values,
+            self.raw.value_size,
+            from_per_cpu_bytes,
+        ))
+    }
+}
+
+/// An `AF_XDP` socket map (`BPF_MAP_TYPE_XSKMAP`), which an XDP program
+/// redirects into with `bpf_redirect_map`. The kernel does not allow reading
+/// socket fds back out of it.
+#[derive(Debug)]
+pub struct XskMap<'obj> {
+    raw: RawMap<'obj>,
+}
+
+impl<'obj> XskMap<'obj> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_XSKMAP],
+            "xskmap",
+            size_of::<u32>(),
+            size_of::<i32>(),
+        )?;
+        Ok(XskMap { raw })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<XskMap<'static>> {
+        XskMap::new(self.raw.map.try_clone()?)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> u32 {
+        self.raw.map.max_entries()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Store the socket fd at `index`, usually the queue the socket is bound
+    /// to.
+    pub fn update(&self, index: u32, sock_fd: i32) -> Result<()> {
+        self.raw
+            .update(as_bytes(&index), as_bytes(&sock_fd), MapFlags::ANY)
+    }
+
+    pub fn delete(&self, index: u32) -> Result<()> {
+        self.raw.delete(as_bytes(&index))
+    }
+}
+
+/// A program array (`BPF_MAP_TYPE_PROG_ARRAY`), the jump table that
+/// `bpf_tail_call` indexes into. Every program in it must have the same type
+/// as the program making the tail call.
+///
+/// The kernel empties the array once no process holds its fd or a pin, even if
+/// a program using it is still attached, so the map or its [Object] must be
+/// kept alive, or the map pinned.
+#[derive(Debug)]
+pub struct ProgArray<'obj> {
+    raw: RawMap<'obj>,
+}
+
+impl<'obj> ProgArray<'obj> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_PROG_ARRAY],
+            "prog_array",
+            size_of::<u32>(),
+            size_of::<u32>(),
+        )?;
+        Ok(ProgArray { raw })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<ProgArray<'static>> {
+        ProgArray::new(self.raw.map.try_clone()?)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> u32 {
+        self.raw.map.max_entries()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The id of the program at `index`, or `None` if the slot is empty.
+    pub fn lookup(&self, index: u32) -> Result<Option<u32>> {
+        let mut id = vec![0; self.raw.value_size];
+        match self.raw.lookup(as_bytes(&index), &mut id)? {
+            true => Ok(Some(from_bytes(&id))),
+            false => Ok(None),
+        }
+    }
+
+    /// Install the program with fd `prog_fd` at `index`, replacing the
+    /// program already there.
+    pub fn update(&self, index: u32, prog_fd: i32) -> Result<()> {
+        self.raw
+            .update(as_bytes(&index), as_bytes(&prog_fd), MapFlags::ANY)
+    }
+
+    /// Install `prog` at `index`.
+    pub fn set(&self, index: u32, prog: &Program) -> Result<()> {
+        self.update(index, prog.fd())
+    }
+
+    /// Empty the slot at `index`, so tail calls to it fall through.
+    pub fn delete(&self, index: u32) -> Result<()> {
+        self.raw.delete(as_bytes(&index))
+    }
+
+    /// Install programs of `obj` by name, as `(index, name)` pairs. Every
+    /// program is looked up before any slot is written, so a missing program
+    /// leaves the array unchanged.
+    ///
+    /// ```ignore
+    /// let jumps = ProgArray::new(obj.find_map("jumps")?)?;
+    /// jumps.install(&obj, &[(IPPROTO_TCP, "parse_tcp"), (IPPROTO_UDP, "parse_udp")])?;
+    /// ```
+    pub fn install(&self, obj: &Object, programs: &[(u32, &str)]) -> Result<()> {
+        install_all(
+            programs,
+            |name| obj.find_program(name),
+            |index, prog| self.set(index, prog),
+        )
+    }
+}
+
+/// An entry of a [DevMap].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct DevMapEntry {
+    /// Interface that packets redirected to the entry are sent out of.
+    pub ifindex: u32,
+    /// Id of the program run on each packet before it is sent.
+    pub prog_id: Option<u32>,
+}
+
+/// An interface map (`BPF_MAP_TYPE_DEVMAP` or `BPF_MAP_TYPE_DEVMAP_HASH`),
+/// which an XDP program redirects into with `bpf_redirect_map` to send
+/// packets out of another interface. The key is an index for `DEVMAP`, and
+/// any `u32` for `DEVMAP_HASH`, usually an ifindex.
+///
+/// Maps whose value is a `struct bpf_devmap_val` can run a second program on
+/// each packet before it is sent. It must be an XDP program loaded with the
+/// `BPF_XDP_DEVMAP` attach type, which libbpf sets for `SEC("xdp/devmap")`.
+/// Maps whose value is a plain `__u32` ifindex can't.
+#[derive(Debug)]
+pub struct DevMap<'obj> {
+    raw: RawMap<'obj>,
+}
+
+impl<'obj> DevMap<'obj> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let value_size = redirect_value_size::<libbpf_sys::bpf_devmap_val>(map.value_size());
+        let raw = RawMap::new(
+            map,
+            &[
+                libbpf_sys::BPF_MAP_TYPE_DEVMAP,
+                libbpf_sys::BPF_MAP_TYPE_DEVMAP_HASH,
+            ],
+            "devmap or devmap_hash",
+            size_of::<u32>(),
+            value_size,
+        )?;
+        Ok(DevMap { raw })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<DevMap<'static>> {
+        DevMap::new(self.raw.map.try_clone()?)
+    }
+
+    /// Whether entries can have a program, because the value is a
+    /// `struct bpf_devmap_val`.
+    #[inline]
+    #[must_use]
+    pub fn has_programs(&self) -> bool {
+        self.raw.value_size > size_of::<u32>()
+    }
+
+    pub fn lookup(&self, key: u32) -> Result<Option<DevMapEntry>> {
+        Ok(lookup_redirect(&self.raw, key)?
+            .map(|(ifindex, prog_id)| DevMapEntry { ifindex, prog_id }))
+    }
+
+    /// Send packets redirected to `key` out of `ifindex`, first running the
+    /// program with fd `prog_fd` on them if there is one.
+    pub fn update(&self, key: u32, ifindex: u32, prog_fd: Option<i32>) -> Result<()> {
+        update_redirect(&self.raw, key, ifindex, prog_fd)
+    }
+
+    pub fn delete(&self, key: u32) -> Result<()> {
+        self.raw.delete(as_bytes(&key))
+    }
+}
+
+/// An entry of a [CpuMap].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct CpuMapEntry {
+    /// Size of the queue that packets wait in for the CPU.
+    pub qsize: u32,
+    /// Id of the program run on each packet on the CPU.
+    pub prog_id: Option<u32>,
+}
+
+/// A CPU map (`BPF_MAP_TYPE_CPUMAP`) indexed by CPU, which an XDP program
+/// redirects into with `bpf_redirect_map` to move the rest of the packet's
+/// processing to another CPU. The map can't have more entries than there are
+/// possible CPUs.
+///
+/// Maps whose value is a `struct bpf_cpumap_val` can run a second program on
+/// the target CPU. It must be an XDP program loaded with the `BPF_XDP_CPUMAP`
+/// attach type, which libbpf sets for `SEC("xdp/cpumap")`.
+#[derive(Debug)]
+pub struct CpuMap<'obj> {
+    raw: RawMap<'obj>,
+}
+
+impl<'obj> CpuMap<'obj> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let value_size = redirect_value_size::<libbpf_sys::bpf_cpumap_val>(map.value_size());
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_CPUMAP],
+            "cpumap",
+            size_of::<u32>(),
+            value_size,
+        )?;
+        Ok(CpuMap { raw })
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<CpuMap<'static>> {
+        CpuMap::new(self.raw.map.try_clone()?)
+    }
+
+    /// Whether entries can have a program, because the value is a
+    /// `struct bpf_cpumap_val`.
+    #[inline]
+    #[must_use]
+    pub fn has_programs(&self) -> bool {
+        self.raw.value_size > size_of::<u32>()
+    }
+
+    pub fn lookup(&self, cpu: u32) -> Result<Option<CpuMapEntry>> {
+        Ok(lookup_redirect(&self.raw, cpu)?.map(|(qsize, prog_id)| CpuMapEntry { qsize, prog_id }))
+    }
+
+    /// Start a kernel thread on `cpu` with a queue of `qsize` packets, which
+    /// runs the program with fd `prog_fd` on them if there is one. A `qsize`
+    /// of zero stops the thread.
+    pub fn update(&self, cpu: u32, qsize: u32, prog_fd: Option<i32>) -> Result<()> {
+        update_redirect(&self.raw, cpu, qsize, prog_fd)
+    }
+
+    pub fn delete(&self, cpu: u32) -> Result<()> {
+        self.raw.delete(as_bytes(&cpu))
+    }
+}
+
+/// An array of maps (`BPF_MAP_TYPE_ARRAY_OF_MAPS`), whose slots hold other
+/// maps. Every inner map must match the outer map's template in type, key and
+/// value sizes and flags, and in max entries unless the template has
+/// `BPF_F_INNER_MAP`.
+///
+/// The kernel doesn't report the template, and libbpf discards the one it
+/// creates for an object once the object is loaded, so the kernel rejects a
+/// mismatch with `EINVAL`. Passing the template to [ArrayOfMaps::with_template]
+/// checks inner maps before they are stored, and names what differs.
+#[derive(Debug)]
+pub struct ArrayOfMaps<'obj> {
+    raw: RawMap<'obj>,
+    template: Option<InnerTemplate>,
+}
+
+impl<'obj> ArrayOfMaps<'obj> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_ARRAY_OF_MAPS],
+            "array_of_maps",
+            size_of::<u32>(),
+            size_of::<u32>(),
+        )?;
+        Ok(ArrayOfMaps {
+            raw,
+            template: None,
+        })
+    }
+
+    /// Check inner maps against `template`, such as the map passed to
+    /// [MapBuilder::inner_map](crate::MapBuilder::inner_map).
+    #[must_use]
+    pub fn with_template(mut self, template: &Map) -> Self {
+        self.template = Some(InnerTemplate::of(template));
+        self
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<ArrayOfMaps<'static>> {
+        Ok(ArrayOfMaps {
+            template: self.template,
+            ..ArrayOfMaps::new(self.raw.map.try_clone()?)?
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> u32 {
+        self.raw.map.max_entries()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The id of the map at `index`, or `None` if the slot is empty.
+    pub fn lookup(&self, index: u32) -> Result<Option<u32>> {
+        let mut id = vec![0; self.raw.value_size];
+        match self.raw.lookup(as_bytes(&index), &mut id)? {
+            true => Ok(Some(from_bytes(&id))),
+            false => Ok(None),
+        }
+    }
+
+    /// Open the map at `index`.
+    pub fn get(&self, index: u32) -> Result<Option<Map<'static>>> {
+        self.lookup(index)?.map(Map::from_id).transpose()
+    }
+
+    /// Store the map with fd `map_fd` at `index`, leaving it to the kernel to
+    /// check that it matches the template.
+    pub fn update(&self, index: u32, map_fd: i32) -> Result<()> {
+        self.raw
+            .update(as_bytes(&index), as_bytes(&map_fd), MapFlags::ANY)
+    }
+
+    /// Store `inner` at `index`, atomically replacing the map there.
+    pub fn set(&self, index: u32, inner: &Map) -> Result<()> {
+        if let Some(template) = &self.template {
+            template.check_map(&self.raw.map, inner)?;
+        }
+        self.update(index, inner.fd())
+    }
+
+    /// Empty the slot at `index`.
+    pub fn delete(&self, index: u32) -> Result<()> {
+        self.raw.delete(as_bytes(&index))
+    }
+}
+
+/// A hash of maps (`BPF_MAP_TYPE_HASH_OF_MAPS`), whose values are other maps.
+/// Inner maps must match the template as for [ArrayOfMaps], and are checked
+/// against one passed to [HashOfMaps::with_template].
+///
+/// Replacing the map under a key is atomic for programs, which see either the
+/// old map or the new one:
+///
+/// ```ignore
+/// let template = MapBuilder::new(BPF_MAP_TYPE_HASH, 8, 4, 1024).build()?;
+/// let tenants = MapBuilder::new(BPF_MAP_TYPE_HASH_OF_MAPS, 4, 4, 64)
+///     .inner_map(&template)
+///     .build()?;
+/// let tenants = HashOfMaps::<u32>::new(tenants)?.with_template(&template);
+///
+/// let rules = MapBuilder::new(BPF_MAP_TYPE_HASH, 8, 4, 1024).build()?;
+/// HashMap::<u64, u32>::new(rules.try_clone()?)?.update(&prefix, &ALLOW, MapFlags::ANY)?;
+/// tenants.set(&tenant, &rules, MapFlags::ANY)?;
+/// ```
+#[derive(Debug)]
+pub struct HashOfMaps<'obj, K> {
+    raw: RawMap<'obj>,
+    template: Option<InnerTemplate>,
+    _key: PhantomData<K>,
+}
+
+impl<'obj, K: Pod> HashOfMaps<'obj, K> {
+    pub fn new(map: Map<'obj>) -> Result<Self> {
+        let raw = RawMap::new(
+            map,
+            &[libbpf_sys::BPF_MAP_TYPE_HASH_OF_MAPS],
+            "hash_of_maps",
+            size_of::<K>(),
+            size_of::<u32>(),
+        )?;
+        Ok(HashOfMaps {
+            raw,
+            template: None,
+            _key: PhantomData,
+        })
+    }
+
+    /// Check inner maps against `template`. See [ArrayOfMaps::with_template].
+    #[must_use]
+    pub fn with_template(mut self, template: &Map) -> Self {
+        self.template = Some(InnerTemplate::of(template));
+        self
+    }
+
+    #[must_use]
+    pub fn map(&self) -> &Map<'obj> {
+        &self.raw.map
+    }
+
+    /// A handle to the same map with its own fd, which stays valid after the
+    /// object is dropped. See [Map::try_clone].
+    pub fn try_clone(&self) -> Result<HashOfMaps<'static, K>> {
+        Ok(HashOfMaps {
+            template: self.template,
+            ..HashOfMaps::new(self.raw.map.try_clone()?)?
+        })
+    }
+
+    /// The id of the map under `key`.
+    pub fn lookup(&self, key: &K) -> Result<Option<u32>> {
+        let mut id = vec![0; self.raw.value_size];
+        match self.raw.lookup(as_bytes(key), &mut id)? {
+            true => Ok(Some(from_bytes(&id))),
+            false => Ok(None),
+        }
+    }
+
+    /// Open the map under `key`.
+    pub fn get(&self, key: &K) -> Result<Option<Map<'static>>> {
+        self.lookup(key)?.map(Map::from_id).transpose()
+    }
+
+    /// Store the map with fd `map_fd` under `key`, leaving it to the kernel to
+    /// check that it matches the template.
+    pub fn update(&self, key: &K, map_fd: i32, flags: MapFlags) -> Result<()> {
+        self.raw.update(as_bytes(key), as_bytes(&map_fd), flags)
+    }
+
+    /// Store `inner` under `key`.
+    pub fn set(&self, key: &K, inner: &Map, flags: MapFlags) -> Result<()> {
+        if let Some(template) = &self.template {
+            template.check_map(&self.raw.map, inner)?;
+        }
+        self.update(key, inner.fd(), flags)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<()> {
+        self.raw.delete(as_bytes(key))
+    }
+
+    /// Iterate over the keys. Entries added or removed concurrently may be
+    /// missed.
+    #[must_use]
+    pub fn keys(&self) -> Keys<'_, K> {
+        Keys::new(&self.raw)
+    }
+}
+
+/// Iterator over the keys of a map, walked with `bpf_map_get_next_key`.
+pub struct Keys<'a, K> {
+    raw: &'a RawMap<'a>,
+    prev: Option<K>,
+    done: bool,
+}
+
+impl<'a, K: Pod> Keys<'a, K> {
+    #[must_use]
+    fn new(raw: &'a RawMap<'a>) -> Self {
+        Keys {
+            raw,
+            prev: None,
+            done: false,
+        }
+    }
+}
+
+impl<K: Pod> Iterator for Keys<'_, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        if self.done {
+            return None;
+        }
+        let mut next = vec![0; self.raw.key_size];
+        match self
+            .raw
+            .next_key(self.prev.as_ref().map(as_bytes), &mut next)
+        {
+            Ok(true) => {
+                let key = from_bytes(&next);
+                self.prev = Some(key);
+                Some(key)
+            }
+            _ => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Byte-level operations through the map's fd, shared by the typed maps.
+#[derive(Debug)]
+struct RawMap<'obj> {
+    map: Map<'obj>,
+    key_size: usize,
+    /// Size of a whole value, which for per-CPU maps covers every CPU.
+    value_size: usize,
+    /// Number of values stored for each key.
+    cpus: usize,
+}
+
+impl<'obj> RawMap<'obj> {
+    fn new(
+        map: Map<'obj>,
+        types: &[libbpf_sys::bpf_map_type],
+        expected: &'static str,
+        key_size: usize,
+        value_size: usize,
+    ) -> Result<Self> {
+        if !types.contains(&map.map_type()) {
+            return Err(Error::MapType {
+                map: map.name(),
+                expected,
+                actual: map.map_type(),
+            });
+        }
+        if map.key_size() as usize != key_size {
+            return Err(Error::MapSize {
+                map: map.name(),
+                kind: "key",
+                expected: key_size,
+                actual: map.key_size(),
+            });
+        }
+        if map.value_size() as usize != value_size {
+            return Err(Error::MapSize {
+                map: map.name(),
+                kind: "value",
+                expected: value_size,
+                actual: map.value_size(),
+            });
+        }
+        Ok(RawMap {
+            map,
+            key_size,
+            value_size,
+            cpus: 1,
+        })
+    }
+
+    /// The kernel copies per-CPU values with each one aligned to 8 bytes.
+    fn per_cpu(mut self) -> Result<Self> {
+        self.cpus = num_possible_cpus()?;
+        self.value_size = per_cpu_stride(self.value_size) * self.cpus;
+        Ok(self)
+    }
+
+    fn per_cpu_values<V: Pod>(&self, values: &[V]) -> Result<Vec<u8>> {
+        if values.len() != self.cpus {
+            return Err(Error::InvalidArgument(
+                "per-CPU update needs one value per possible CPU",
+            ));
+        }
+        Ok(to_per_cpu_bytes(values))
+    }
+
+    fn lookup(&self, key: &[u8], value: &mut [u8]) -> Result<bool> {
+        match unsafe {
+            libbpf_sys::bpf_map_lookup_elem(
+                self.map.fd(),
+                key.as_ptr() as _,
+                value.as_mut_ptr() as _,
+            )
+        } {
+            ret if ret == -libc::ENOENT => Ok(false),
+            ret if ret < 0 => Err(self.lookup_error(ret)),
+            _ => Ok(true),
+        }
+    }
+
+    /// Arrays have no missing keys, so `ENOENT` means the index is out of
+    /// bounds.
+    fn lookup_index(&self, index: u32, value: &mut [u8]) -> Result<()> {
+        match self.lookup(as_bytes(&index), value)? {
+            true => Ok(()),
+            false => Err(self.lookup_error(-libc::ENOENT)),
+        }
+    }
+
+    fn update(&self, key: &[u8], value: &[u8], flags: MapFlags) -> Result<()> {
+        match unsafe {
+            libbpf_sys::bpf_map_update_elem(
+                self.map.fd(),
+                key.as_ptr() as _,
+                value.as_ptr() as _,
+                flags.bits(),
+            )
+        } {
+            ret if ret < 0 => Err(self.update_error(ret)),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        match unsafe { libbpf_sys::bpf_map_delete_elem(self.map.fd(), key.as_ptr() as _) } {
+            ret if ret < 0 => Err(self.delete_error(ret)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the key after `key`, or the first key if it is `None`, returning
+    /// false once there are no more keys.
+    fn next_key(&self, key: Option<&[u8]>, next: &mut [u8]) -> Result<bool> {
+        let key = key.map_or(std::ptr::null(), |key| key.as_ptr());
+        match unsafe {
+            libbpf_sys::bpf_map_get_next_key(self.map.fd(), key as _, next.as_mut_ptr() as _)
+        } {
+            ret if ret == -libc::ENOENT => Ok(false),
+            ret if ret < 0 => Err(self.lookup_error(ret)),
+            _ => Ok(true),
+        }
+    }
+
+    /// Read every entry, returning the packed keys and values.
+    fn lookup_batch(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let capacity = self.map.max_entries() as usize;
+        let mut keys = vec![0; capacity * self.key_size];
+        let mut values = vec![0; capacity * self.value_size];
+        // Hash maps use a bucket index as the batch token, other maps a key.
+        let mut batch = vec![0u8; self.key_size.max(size_of::<u32>())];
+        let opts = batch_opts(MapFlags::ANY);
+
+        let mut total = 0;
+        let mut first = true;
+        while total < capacity {
+            let mut count = (capacity - total) as u32;
+            let in_batch = match first {
+                true => null_mut(),
+                false => batch.as_mut_ptr(),
+            };
+            let ret = unsafe {
+                libbpf_sys::bpf_map_lookup_batch(
+                    self.map.fd(),
+                    in_batch as _,
+                    batch.as_mut_ptr() as _,
+                    keys[total * self.key_size..].as_mut_ptr() as _,
+                    values[total * self.value_size..].as_mut_ptr() as _,
+                    &mut count,
+                    &opts,
+                )
+            };
+            total += count as usize;
+            first = false;
+            match ret {
+                ret if ret == -libc::ENOENT => break,
+                ret if ret < 0 => return Err(self.lookup_error(ret)),
+                _ => {}
+            }
+        }
+
+        keys.truncate(total * self.key_size);
+        values.truncate(total * self.value_size);
+        Ok((keys, values))
+    }
+
+    fn update_batch(&self, keys: &[u8], values: &[u8], flags: MapFlags) -> Result<()> {
+        let mut count = (keys.len() / self.key_size) as u32;
+        let opts = batch_opts(flags);
+        match unsafe {
+            libbpf_sys::bpf_map_update_batch(
+                self.map.fd(),
+                keys.as_ptr() as _,
+                values.as_ptr() as _,
+                &mut count,
+                &opts,
+            )
+        } {
+            ret if ret < 0 => Err(self.update_error(ret)),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete_batch(&self, keys: &[u8]) -> Result<()> {
+        let mut count = (keys.len() / self.key_size) as u32;
+        let opts = batch_opts(MapFlags::ANY);
+        match unsafe {
+            libbpf_sys::bpf_map_delete_batch(self.map.fd(), keys.as_ptr() as _, &mut count, &opts)
+        } {
+            ret if ret < 0 => Err(self.delete_error(ret)),
+            _ => Ok(()),
+        }
+    }
+
+    #[must_use]
+    fn lookup_error(&self, ret: i32) -> Error {
+        Error::MapLookup {
+            errno: Errno::from_ret(ret),
+            map: self.map.name(),
+        }
+    }
+
+    #[must_use]
+    fn update_error(&self, ret: i32) -> Error {
+        Error::MapUpdate {
+            errno: Errno::from_ret(ret),
+            map: self.map.name(),
+        }
+    }
+
+    #[must_use]
+    fn delete_error(&self, ret: i32) -> Error {
+        Error::MapDelete {
+            errno: Errno::from_ret(ret),
+            map: self.map.name(),
+        }
+    }
+}
+
+/// Find every program by name before installing any, for
+/// [ProgArray::install].
+fn install_all<P>(
+    programs: &[(u32, &str)],
+    find: impl Fn(&str) -> Result<P>,
+    mut set: impl FnMut(u32, &P) -> Result<()>,
+) -> Result<()> {
+    let programs = programs
+        .iter()
+        .map(|&(index, name)| Ok((index, find(name)?)))
+        .collect::<Result<Vec<_>>>()?;
+    programs
+        .iter()
+        .try_for_each(|(index, prog)| set(*index, prog))
+}
+
+/// The value of a devmap or cpumap is either a `__u32`, or a struct of a
+/// `__u32` and a program. A map of any other size is reported as not matching
+/// the struct.
+#[must_use]
+fn redirect_value_size<T>(value_size: u32) -> usize {
+    match value_size as usize {
+        size if size == size_of::<u32>() => size,
+        _ => size_of::<T>(),
+    }
+}
+
+/// Look up a `bpf_devmap_val` or `bpf_cpumap_val`, for which the kernel
+/// returns the id of the program in place of the fd.
+fn lookup_redirect(raw: &RawMap, key: u32) -> Result<Option<(u32, Option<u32>)>> {
+    let mut value = [0u32; 2];
+    let len = raw.value_size;
+    match raw.lookup(as_bytes(&key), &mut slice_as_bytes_mut(&mut value)[..len])? {
+        true => Ok(Some((value[0], Some(value[1]).filter(|&id| id != 0)))),
+        false => Ok(None),
+    }
+}
+
+fn update_redirect(raw: &RawMap, key: u32, first: u32, prog_fd: Option<i32>) -> Result<()> {
+    let value = redirect_value(raw.value_size, first, prog_fd)?;
+    raw.update(
+        as_bytes(&key),
+        &as_bytes(&value)[..raw.value_size],
+        MapFlags::ANY,
+    )
+}
+
+/// A devmap or cpumap value of `value_size`, of which only the first `__u32`
+/// is written to a map whose value has no program.
+fn redirect_value(value_size: usize, first: u32, prog_fd: Option<i32>) -> Result<[u32; 2]> {
+    if prog_fd.is_some() && value_size == size_of::<u32>() {
+        return Err(Error::InvalidArgument(
+            "map value has no program, it must be a bpf_devmap_val or bpf_cpumap_val",
+        ));
+    }
+    // The kernel only opens the program if the fd is positive.
+    Ok([first, prog_fd.unwrap_or(0) as u32])
+}
+
+/// The shape every inner map of a map-in-map must have.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct InnerTemplate {
+    map_type: libbpf_sys::bpf_map_type,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    flags: u32,
+}
+
+impl InnerTemplate {
+    #[must_use]
+    fn of(map: &Map) -> Self {
+        InnerTemplate {
+            map_type: map.map_type(),
+            key_size: map.key_size(),
+            value_size: map.value_size(),
+            max_entries: map.max_entries(),
+            flags: map.map_flags(),
+        }
+    }
+
+    /// Check `inner` as the kernel will, to report which property differs.
+    fn check_map(&self, outer: &Map, inner: &Map) -> Result<()> {
+        self.check(InnerTemplate::of(inner))
+            .map_err(|(kind, expected, actual)| Error::InnerMap {
+                map: outer.name(),
+                inner: inner.name(),
+                kind,
+                expected,
+                actual,
+            })
+    }
+
+    /// The first property of `actual` that differs from the template, with
+    /// its expected and actual values.
+    fn check(
+        &self,
+        actual: InnerTemplate,
+    ) -> std::result::Result<(), (&'static str, String, String)> {
+        if actual.map_type != self.map_type {
+            return Err((
+                "type",
+                map_type_name(self.map_type),
+                map_type_name(actual.map_type),
+            ));
+        }
+        let sizes = [
+            ("key size", self.key_size, actual.key_size),
+            ("value size", self.value_size, actual.value_size),
+            ("flags", self.flags, actual.flags),
+        ];
+        for (kind, expected, actual) in sizes {
+            if expected != actual {
+                return Err((kind, expected.to_string(), actual.to_string()));
+            }
+        }
+        // BPF_F_INNER_MAP lets arrays of any size share a template.
+        let any_size = self.flags & libbpf_sys::BPF_F_INNER_MAP != 0;
+        if !any_size && actual.max_entries != self.max_entries {
+            return Err((
+                "max entries",
+                self.max_entries.to_string(),
+                actual.max_entries.to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[must_use]
+fn batch_opts(flags: MapFlags) -> libbpf_sys::bpf_map_batch_opts {
+    libbpf_sys::bpf_map_batch_opts {
+        sz: size_of::<libbpf_sys::bpf_map_batch_opts>() as _,
+        elem_flags: flags.bits(),
+        flags: 0,
+    }
+}
+
+#[inline]
+#[must_use]
+fn per_cpu_stride(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+#[must_use]
+pub(crate) fn as_bytes<T: Pod>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+#[must_use]
+fn slice_as_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values)) }
+}
+
+#[must_use]
+fn slice_as_bytes_mut<T: Pod>(values: &mut [T]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, size_of_val(values)) }
+}
+
+#[must_use]
+pub(crate) fn from_bytes<T: Pod>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+#[must_use]
+fn from_per_cpu_bytes<V: Pod>(bytes: &[u8]) -> Vec<V> {
+    bytes
+        .chunks_exact(per_cpu_stride(size_of::<V>()))
+        .map(from_bytes)
+        .collect()
+}
+
+#[must_use]
+fn to_per_cpu_bytes<V: Pod>(values: &[V]) -> Vec<u8> {
+    let stride = per_cpu_stride(size_of::<V>());
+    let mut bytes = vec![0; stride * values.len()];
+    for (chunk, value) in bytes.chunks_exact_mut(stride).zip(values) {
+        chunk[..size_of::<V>()].copy_from_slice(as_bytes(value));
+    }
+    bytes
+}
+
+#[must_use]
+fn zip_entries<K: Pod, V>(
+    keys: &[u8],
+    values: &[u8],
+    value_size: usize,
+    decode: impl Fn(&[u8]) -> V,
+) -> Vec<(K, V)> {
+    keys.chunks_exact(size_of::<K>())
+        .map(from_bytes)
+        .zip(values.chunks_exact(value_size).map(decode))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_cpu_values_are_padded_to_8_bytes() {
+        let values: [u32; 3] = [1, 2, 3];
+
+        let bytes = to_per_cpu_bytes(&values);
+
+        assert_eq!(24, bytes.len());
+        assert_eq!(values.to_vec(), from_per_cpu_bytes::<u32>(&bytes));
+    }
+
+    #[test]
+    fn test_zip_entries_decodes_packed_batch() {
+        let keys = slice_as_bytes(&[1u32, 2]).to_vec();
+        let values = slice_as_bytes(&[10u64, 20]).to_vec();
+
+        let entries: Vec<(u32, u64)> = zip_entries(&keys, &values, 8, from_bytes);
+
+        assert_eq!(vec![(1, 10), (2, 20)], entries);
+    }
+
+    #[test]
+    fn test_map_flags_combine() {
+        assert_eq!(
+            (libbpf_sys::BPF_EXIST | libbpf_sys::BPF_F_LOCK) as u64,
+            (MapFlags::EXIST | MapFlags::LOCK).bits()
+        );
+    }
+
+    #[test]
+    fn test_install_finds_every_program_before_setting_any() {
+        let find = |name: &str| match name {
+            "parse_tcp" | "parse_udp" => Ok(name.to_owned()),
+            _ => Err(Error::ProgramNotFound(name.to_owned())),
+        };
+        let mut installed = vec![];
+
+        let result = install_all(
+            &[(6, "parse_tcp"), (132, "parse_sctp")],
+            find,
+            |index, name| {
+                installed.push((index, name.clone()));
+                Ok(())
+            },
+        );
+
+        assert!(matches!(result, Err(Error::ProgramNotFound(name)) if name == "parse_sctp"));
+        assert!(installed.is_empty());
+
+        install_all(
+            &[(6, "parse_tcp"), (17, "parse_udp")],
+            find,
+            |index, name| {
+                installed.push((index, name.clone()));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![(6, "parse_tcp".to_owned()), (17, "parse_udp".to_owned())],
+            installed
+        );
+    }
+
+    #[test]
+    fn test_redirect_value_size() {
+        assert_eq!(4, redirect_value_size::<libbpf_sys::bpf_devmap_val>(4));
+        assert_eq!(8, redirect_value_size::<libbpf_sys::bpf_devmap_val>(8));
+        assert_eq!(4, redirect_value_size::<libbpf_sys::bpf_cpumap_val>(4));
+        assert_eq!(8, redirect_value_size::<libbpf_sys::bpf_cpumap_val>(8));
+        // Reported as a mismatch against the struct.
+        assert_eq!(8, redirect_value_size::<libbpf_sys::bpf_devmap_val>(12));
+    }
+
+    #[test]
+    fn test_redirect_value_rejects_program_without_room() {
+        assert!(matches!(
+            redirect_value(4, 2, Some(7)),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!([2, 0], redirect_value(4, 2, None).unwrap());
+        assert_eq!([2, 7], redirect_value(8, 2, Some(7)).unwrap());
+        assert_eq!([2, 0], redirect_value(8, 2, None).unwrap());
+    }
+
+    #[test]
+    fn test_inner_template_check() {
+        let template = InnerTemplate {
+            map_type: libbpf_sys::BPF_MAP_TYPE_ARRAY,
+            key_size: 4,
+            value_size: 8,
+            max_entries: 16,
+            flags: 0,
+        };
+        let kind = |actual| template.check(actual).map_err(|(kind, ..)| kind);
+
+        assert_eq!(Ok(()), template.check(template));
+        assert_eq!(
+            Err(("type", "array".into(), "hash".into())),
+            template.check(InnerTemplate {
+                map_type: libbpf_sys::BPF_MAP_TYPE_HASH,
+                ..template
+            })
+        );
+        let rdonly = InnerTemplate {
+            flags: libbpf_sys::BPF_F_RDONLY,
+            ..template
+        };
+        assert_eq!(Err("flags"), kind(rdonly));
+        let resized = InnerTemplate {
+            max_entries: 32,
+            ..template
+        };
+        assert_eq!(Err("max entries"), kind(resized));
+
+        let any_size = InnerTemplate {
+            flags: libbpf_sys::BPF_F_INNER_MAP,
+            ..template
+        };
+        let resized = InnerTemplate {
+            max_entries: 32,
+            ..any_size
+        };
+        assert_eq!(Ok(()), any_size.check(resized));
+    }
+}
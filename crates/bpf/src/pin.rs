@@ -0,0 +1,198 @@
+//! Pinning programs, maps and links to bpffs so they outlive the process that
+//! loaded them. A daemon can pin its link and maps on the first run, and open
+//! them again after a restart without detaching the program or losing state.
+//!
+//! ```ignore
+//! let link = match LinkedProgram::from_pinned("/sys/fs/bpf/xdp/link") {
+//!     Ok(link) => link,
+//!     Err(_) => {
+//!         let link = obj.find_program("xdp_prog")?.attach_xdp(ifindex)?;
+//!         link.pin("/sys/fs/bpf/xdp/link")?;
+//!         link
+//!     }
+//! };
+//! let counters = Map::from_pinned("/sys/fs/bpf/xdp/counters")?;
+//! ```
+//!
+//! Unpinning only needs the path, so it works without opening the object:
+//! `LinkedProgram::unpin("/sys/fs/bpf/xdp/link")?` detaches the program once
+//! no process holds the link.
+//!
+//! Maps declared with `__uint(pinning, LIBBPF_PIN_BY_NAME)` are pinned by
+//! libbpf when the object is loaded, and reused if a compatible map is
+//! already pinned. They go under `/sys/fs/bpf` unless the object was created
+//! with [Object::create_pinned] or [ObjectBuilder::pin_root].
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use crate::{
+    obj_get_info, path_to_cstring, Errno, Error, LinkedProgram, Map, MapMut, Object, ObjectBuilder,
+    Program, Result, TcHook, TcLink,
+};
+
+impl Object {
+    /// Create a BPF object whose `LIBBPF_PIN_BY_NAME` maps are pinned under
+    /// `pin_root` instead of `/sys/fs/bpf`.
+    pub fn create_pinned<P: AsRef<Path>>(obj_buf: &[u8], pin_root: P) -> Result<Object> {
+        ObjectBuilder::new().pin_root(pin_root).open_memory(obj_buf)
+    }
+}
+
+impl Map<'static> {
+    /// Open a map pinned at `path`.
+    pub fn from_pinned<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let fd = obj_get(path.as_ref())?;
+        Map::from_fd(fd).map_err(|errno| open_pinned_error(errno, path.as_ref()))
+    }
+
+    /// Remove the pin at `path`. The map is freed once nothing else holds it.
+    pub fn unpin<P: AsRef<Path>>(path: P) -> Result<()> {
+        unlink(path.as_ref())
+    }
+}
+
+impl Map<'_> {
+    /// Pin the map at `path`, which must be on a bpffs mount. Maps owned by an
+    /// object must be pinned after the object is loaded.
+    pub fn pin<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let c_path = path_to_cstring(path.as_ref())?;
+        let ret = unsafe { libbpf_sys::bpf_obj_pin(self.fd(), c_path.as_ptr()) };
+        pin_result(ret, path.as_ref())
+    }
+}
+
+impl MapMut<'_> {
+    /// Set where the map is pinned when its object is loaded. If a map is
+    /// already pinned there it is reused instead of creating a new one.
+    pub fn set_pin_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let c_path = path_to_cstring(path.as_ref())?;
+        let ret = unsafe { libbpf_sys::bpf_map__set_pin_path(self.map.map, c_path.as_ptr()) };
+        pin_result(ret, path.as_ref())
+    }
+}
+
+impl Program<'static> {
+    /// Open a program pinned at `path`.
+    pub fn from_pinned<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let fd = obj_get(path.as_ref())?;
+        Program::from_fd(fd).map_err(|errno| open_pinned_error(errno, path.as_ref()))
+    }
+
+    /// Remove the pin at `path`. The program is unloaded once nothing else
+    /// holds it.
+    pub fn unpin<P: AsRef<Path>>(path: P) -> Result<()> {
+        unlink(path.as_ref())
+    }
+}
+
+impl Program<'_> {
+    /// Pin the loaded program at `path`, which must be on a bpffs mount.
+    pub fn pin<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let c_path = path_to_cstring(path.as_ref())?;
+        let ret = unsafe { libbpf_sys::bpf_obj_pin(self.fd(), c_path.as_ptr()) };
+        pin_result(ret, path.as_ref())
+    }
+}
+
+impl LinkedProgram {
+    /// Open an XDP `bpf_link` pinned at `path`. The program stays attached,
+    /// and is detached when the returned link is dropped only if the pin has
+    /// been removed.
+    pub fn from_pinned<P: AsRef<Path>>(path: P) -> Result<LinkedProgram> {
+        let fd = obj_get(path.as_ref())?;
+        let info: libbpf_sys::bpf_link_info = obj_get_info(fd.as_raw_fd())
+            .map_err(|errno| open_pinned_error(errno, path.as_ref()))?;
+        if info.type_ != libbpf_sys::BPF_LINK_TYPE_XDP {
+            return Err(Error::InvalidArgument("pinned link is not an XDP link"));
+        }
+        let ifindex = unsafe { info.__bindgen_anon_1.xdp.ifindex };
+        Ok(LinkedProgram::from_link_fd(fd, ifindex))
+    }
+
+    /// Pin the link at `path`, keeping the program attached after the link is
+    /// dropped. Only programs attached with [Program::attach_xdp] have a link
+    /// that can be pinned.
+    pub fn pin<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let fd = self.link_fd().ok_or(Error::InvalidArgument(
+            "only programs attached with a bpf_link can be pinned",
+        ))?;
+        let c_path = path_to_cstring(path.as_ref())?;
+        let ret = unsafe { libbpf_sys::bpf_obj_pin(fd, c_path.as_ptr()) };
+        pin_result(ret, path.as_ref())
+    }
+
+    /// Remove the pin at `path`. The program is detached when the last link
+    /// fd is closed.
+    pub fn unpin<P: AsRef<Path>>(path: P) -> Result<()> {
+        unlink(path.as_ref())
+    }
+}
+
+impl TcLink {
+    /// Open a tcx link pinned at `path`, with the same lifecycle as
+    /// [LinkedProgram::from_pinned].
+    pub fn from_pinned<P: AsRef<Path>>(path: P) -> Result<TcLink> {
+        let fd = obj_get(path.as_ref())?;
+        let info: libbpf_sys::bpf_link_info = obj_get_info(fd.as_raw_fd())
+            .map_err(|errno| open_pinned_error(errno, path.as_ref()))?;
+        if info.type_ != libbpf_sys::BPF_LINK_TYPE_TCX {
+            return Err(Error::InvalidArgument("pinned link is not a tcx link"));
+        }
+        let tcx = unsafe { info.__bindgen_anon_1.tcx };
+        let hook = match tcx.attach_type {
+            libbpf_sys::BPF_TCX_EGRESS => TcHook::Egress,
+            _ => TcHook::Ingress,
+        };
+        Ok(TcLink::from_link_fd(fd, tcx.ifindex, hook))
+    }
+
+    /// Pin the link at `path`, keeping the program attached after the link is
+    /// dropped. Only programs attached with a tcx link can be pinned.
+    pub fn pin<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let fd = self.link_fd().ok_or(Error::InvalidArgument(
+            "only programs attached with a tcx link can be pinned",
+        ))?;
+        let c_path = path_to_cstring(path.as_ref())?;
+        let ret = unsafe { libbpf_sys::bpf_obj_pin(fd, c_path.as_ptr()) };
+        pin_result(ret, path.as_ref())
+    }
+
+    /// Remove the pin at `path`. The program is detached when the last link
+    /// fd is closed.
+    pub fn unpin<P: AsRef<Path>>(path: P) -> Result<()> {
+        unlink(path.as_ref())
+    }
+}
+
+pub(crate) fn obj_get(path: &Path) -> Result<OwnedFd> {
+    let c_path = path_to_cstring(path)?;
+    match unsafe { libbpf_sys::bpf_obj_get(c_path.as_ptr()) } {
+        ret if ret < 0 => Err(open_pinned_error(Errno::from_ret(ret), path)),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
+
+pub(crate) fn unlink(path: &Path) -> Result<()> {
+    std::fs::remove_file(path).map_err(|err| Error::Pin {
+        errno: Errno(err.raw_os_error().unwrap_or(libc::EIO)),
+        path: path.display().to_string(),
+    })
+}
+
+pub(crate) fn pin_result(ret: i32, path: &Path) -> Result<()> {
+    match ret {
+        ret if ret < 0 => Err(Error::Pin {
+            errno: Errno::from_ret(ret),
+            path: path.display().to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[must_use]
+fn open_pinned_error(errno: Errno, path: &Path) -> Error {
+    Error::OpenPinned {
+        errno,
+        path: path.display().to_string(),
+    }
+}
@@ -0,0 +1,350 @@
+//! Listing the programs and maps loaded in the kernel, as `bpftool prog` and
+//! `bpftool map` do, and opening them by id. Listing and opening by id require
+//! CAP_SYS_ADMIN.
+//!
+//! ```ignore
+//! for (mode, id) in query::xdp_attached(ifindex)? {
+//!     let info = Program::from_id(id)?.info()?;
+//!     println!("{mode:?}: {info}");
+//! }
+//!
+//! for map in query::maps()? {
+//!     println!("{map}");
+//! }
+//! ```
+//!
+//! The kernel only counts runs and run time while stats are enabled, so
+//! [ProgramInfo::run_count] and [ProgramInfo::run_time] stay at zero
+//! unless a [StatsGuard](crate::StatsGuard) is held.
+use std::fmt;
+use std::mem::size_of;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use crate::error::{map_type_name, prog_type_name};
+use crate::{cstr_to_string, obj_get_info, Errno, Error, Map, Program, Result, RunStats, XdpMode};
+
+/// A loaded program, as the kernel reports it in `bpf_prog_info`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProgramInfo {
+    pub id: u32,
+    pub name: String,
+    /// The `BPF_PROG_TYPE_*` of the program.
+    pub prog_type: libbpf_sys::bpf_prog_type,
+    /// Hash of the program's instructions, which identifies the same program
+    /// loaded twice.
+    pub tag: [u8; 8],
+    /// Time since boot when the program was loaded.
+    pub load_time: Duration,
+    pub uid: u32,
+    pub btf_id: u32,
+    pub map_ids: Vec<u32>,
+    pub run_count: u64,
+    pub run_time: Duration,
+    /// Bytes of memory the program is charged for, if the kernel reports it.
+    pub memlock: Option<u64>,
+}
+
+impl ProgramInfo {
+    /// The tag in hex, as bpftool prints it.
+    #[must_use]
+    pub fn tag_hex(&self) -> String {
+        self.tag.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// Average time of a run, if the program has run while stats were
+    /// enabled.
+    #[must_use]
+    pub fn average_run_time(&self) -> Option<Duration> {
+        self.run_stats().average()
+    }
+
+    /// The run count and run time, to compare with a later [Program::run_stats].
+    #[must_use]
+    pub fn run_stats(&self) -> RunStats {
+        RunStats {
+            run_count: self.run_count,
+            run_time: self.run_time,
+        }
+    }
+}
+
+impl fmt::Display for ProgramInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} name {} tag {}",
+            self.id,
+            prog_type_name(self.prog_type),
+            self.name,
+            self.tag_hex()
+        )?;
+        if self.run_count > 0 {
+            write!(
+                f,
+                " run_cnt {} run_time_ns {}",
+                self.run_count,
+                self.run_time.as_nanos()
+            )?;
+        }
+        if let Some(memlock) = self.memlock {
+            write!(f, " memlock {memlock}B")?;
+        }
+        Ok(())
+    }
+}
+
+/// A loaded map, as the kernel reports it in `bpf_map_info`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MapInfo {
+    pub id: u32,
+    pub name: String,
+    /// The `BPF_MAP_TYPE_*` of the map.
+    pub map_type: libbpf_sys::bpf_map_type,
+    pub key_size: u32,
+    pub value_size: u32,
+    pub max_entries: u32,
+    pub map_flags: u32,
+    pub btf_id: u32,
+    /// Bytes of memory the map is charged for, if the kernel reports it.
+    pub memlock: Option<u64>,
+}
+
+impl fmt::Display for MapInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} name {} key {}B value {}B max_entries {}",
+            self.id,
+            map_type_name(self.map_type),
+            self.name,
+            self.key_size,
+            self.value_size,
+            self.max_entries
+        )?;
+        if let Some(memlock) = self.memlock {
+            write!(f, " memlock {memlock}B")?;
+        }
+        Ok(())
+    }
+}
+
+impl Program<'_> {
+    /// What the kernel reports about the program. The object must have been
+    /// loaded.
+    pub fn info(&self) -> Result<ProgramInfo> {
+        let fd = self.fd();
+        let info: libbpf_sys::bpf_prog_info = obj_get_info(fd).map_err(Error::Errno)?;
+
+        // The map ids are only copied out to a buffer of the right size.
+        let mut map_ids = vec![0u32; info.nr_map_ids as usize];
+        if !map_ids.is_empty() {
+            let mut ids_info = libbpf_sys::bpf_prog_info {
+                nr_map_ids: map_ids.len() as u32,
+                map_ids: map_ids.as_mut_ptr() as u64,
+                ..Default::default()
+            };
+            let mut len = size_of::<libbpf_sys::bpf_prog_info>() as u32;
+            let ret = unsafe {
+                libbpf_sys::bpf_obj_get_info_by_fd(fd, &mut ids_info as *mut _ as *mut _, &mut len)
+            };
+            if ret < 0 {
+                return Err(Error::Errno(Errno::from_ret(ret)));
+            }
+            // Maps may have been released in between.
+            map_ids.truncate(ids_info.nr_map_ids as usize);
+        }
+
+        Ok(ProgramInfo {
+            id: info.id,
+            name: unsafe { cstr_to_string(info.name.as_ptr()) },
+            prog_type: info.type_,
+            tag: info.tag,
+            load_time: Duration::from_nanos(info.load_time),
+            uid: info.created_by_uid,
+            btf_id: info.btf_id,
+            map_ids,
+            run_count: info.run_cnt,
+            run_time: Duration::from_nanos(info.run_time_ns),
+            memlock: memlock(fd),
+        })
+    }
+}
+
+impl Program<'static> {
+    /// Open a loaded program by its id.
+    pub fn from_id(id: u32) -> Result<Self> {
+        let open_error = |errno| Error::OpenId {
+            errno,
+            kind: "program",
+            id,
+        };
+        match unsafe { libbpf_sys::bpf_prog_get_fd_by_id(id) } {
+            ret if ret < 0 => Err(open_error(Errno::from_ret(ret))),
+            fd => Program::from_fd(unsafe { OwnedFd::from_raw_fd(fd) }).map_err(open_error),
+        }
+    }
+}
+
+impl Map<'_> {
+    /// What the kernel reports about the map. The object must have been
+    /// loaded.
+    pub fn info(&self) -> Result<MapInfo> {
+        let fd = self.fd();
+        let info: libbpf_sys::bpf_map_info = obj_get_info(fd).map_err(Error::Errno)?;
+        Ok(MapInfo {
+            id: info.id,
+            name: unsafe { cstr_to_string(info.name.as_ptr()) },
+            map_type: info.type_,
+            key_size: info.key_size,
+            value_size: info.value_size,
+            max_entries: info.max_entries,
+            map_flags: info.map_flags,
+            btf_id: info.btf_id,
+            memlock: memlock(fd),
+        })
+    }
+}
+
+impl Map<'static> {
+    /// Open a map by its id.
+    pub fn from_id(id: u32) -> Result<Self> {
+        let open_error = |errno| Error::OpenId {
+            errno,
+            kind: "map",
+            id,
+        };
+        match unsafe { libbpf_sys::bpf_map_get_fd_by_id(id) } {
+            ret if ret < 0 => Err(open_error(Errno::from_ret(ret))),
+            fd => Map::from_fd(unsafe { OwnedFd::from_raw_fd(fd) }).map_err(open_error),
+        }
+    }
+}
+
+/// Ids of every loaded program, in increasing order.
+pub fn program_ids() -> Result<Vec<u32>> {
+    ids("program", |start, next| unsafe {
+        libbpf_sys::bpf_prog_get_next_id(start, next)
+    })
+}
+
+/// Ids of every loaded map, in increasing order.
+pub fn map_ids() -> Result<Vec<u32>> {
+    ids("map", |start, next| unsafe {
+        libbpf_sys::bpf_map_get_next_id(start, next)
+    })
+}
+
+/// Every loaded program. Programs unloaded while listing are left out.
+pub fn programs() -> Result<Vec<ProgramInfo>> {
+    program_ids()?
+        .into_iter()
+        .filter_map(|id| match Program::from_id(id) {
+            Ok(prog) => Some(prog.info()),
+            Err(err) if err.errno() == Some(Errno(libc::ENOENT)) => None,
+            Err(err) => Some(Err(err)),
+        })
+        .collect()
+}
+
+/// Every loaded map. Maps released while listing are left out.
+pub fn maps() -> Result<Vec<MapInfo>> {
+    map_ids()?
+        .into_iter()
+        .filter_map(|id| match Map::from_id(id) {
+            Ok(map) => Some(map.info()),
+            Err(err) if err.errno() == Some(Errno(libc::ENOENT)) => None,
+            Err(err) => Some(Err(err)),
+        })
+        .collect()
+}
+
+/// Ids of the XDP programs attached to the interface, by the mode they are
+/// attached in. An interface can have a generic, a native and an offloaded
+/// program at once.
+pub fn xdp_attached(ifindex: u32) -> Result<Vec<(XdpMode, u32)>> {
+    let mut opts = libbpf_sys::bpf_xdp_query_opts {
+        sz: size_of::<libbpf_sys::bpf_xdp_query_opts>() as _,
+        ..Default::default()
+    };
+    match unsafe { libbpf_sys::bpf_xdp_query(ifindex as i32, 0, &mut opts) } {
+        ret if ret < 0 => Err(Error::Errno(Errno::from_ret(ret))),
+        _ => Ok([
+            (XdpMode::Generic, opts.skb_prog_id),
+            (XdpMode::Native, opts.drv_prog_id),
+            (XdpMode::Hardware, opts.hw_prog_id),
+        ]
+        .into_iter()
+        .filter(|&(_, id)| id != 0)
+        .collect()),
+    }
+}
+
+/// Walk ids with a `bpf_*_get_next_id` function until it returns `ENOENT`.
+fn ids(kind: &'static str, next_id: impl Fn(u32, &mut u32) -> i32) -> Result<Vec<u32>> {
+    let mut ids = vec![];
+    let mut id = 0;
+    loop {
+        match next_id(id, &mut id) {
+            ret if ret == -libc::ENOENT => return Ok(ids),
+            ret if ret < 0 => {
+                return Err(Error::List {
+                    errno: Errno::from_ret(ret),
+                    kind,
+                })
+            }
+            _ => ids.push(id),
+        }
+    }
+}
+
+/// The memory a program or map is charged for, from its fdinfo.
+#[must_use]
+fn memlock(fd: i32) -> Option<u64> {
+    let fdinfo = std::fs::read_to_string(format!("/proc/self/fdinfo/{fd}")).ok()?;
+    parse_memlock(&fdinfo)
+}
+
+#[must_use]
+fn parse_memlock(fdinfo: &str) -> Option<u64> {
+    fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("memlock:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memlock_from_fdinfo() {
+        let fdinfo = "pos:\t0\nflags:\t02000002\nmnt_id:\t16\nino:\t2070\nprog_type:\t6\n\
+                      prog_jited:\t1\nprog_tag:\t3b185187f1855c4c\nmemlock:\t4096\nprog_id:\t52\n";
+
+        assert_eq!(Some(4096), parse_memlock(fdinfo));
+        assert_eq!(None, parse_memlock("pos:\t0\n"));
+    }
+
+    #[test]
+    fn test_average_run_time() {
+        let mut info = ProgramInfo {
+            id: 1,
+            name: "xsk_redirect".into(),
+            prog_type: libbpf_sys::BPF_PROG_TYPE_XDP,
+            tag: [0x3b, 0x18, 0x51, 0x87, 0xf1, 0x85, 0x5c, 0x4c],
+            load_time: Duration::ZERO,
+            uid: 0,
+            btf_id: 0,
+            map_ids: vec![],
+            run_count: 0,
+            run_time: Duration::from_nanos(900),
+            memlock: None,
+        };
+        assert_eq!(None, info.average_run_time());
+
+        info.run_count = 3;
+        assert_eq!(Some(Duration::from_nanos(300)), info.average_run_time());
+        assert_eq!("3b185187f1855c4c", info.tag_hex());
+    }
+}
@@ -0,0 +1,655 @@
+//! Generating Rust code for a BPF object from its BTF, like
+//! `bpftool gen skeleton` does for C. The generated module has a Rust type for
+//! every map key and value and global variable, and a skeleton with one field
+//! per program and map, so a change on the C side fails to compile instead of
+//! failing at run time.
+//!
+//! The generator is meant to run in a build script, on an object compiled
+//! with `-g` so that it has BTF:
+//!
+//! ```ignore
+//! // build.rs
+//! let obj = std::fs::read("bpf/filter.o")?;
+//! let skel = bpf::skel::SkeletonGenerator::new("filter").generate(&obj)?;
+//! std::fs::write(Path::new(&env::var("OUT_DIR")?).join("filter.skel.rs"), skel)?;
+//!
+//! // main.rs
+//! include!(concat!(env!("OUT_DIR"), "/filter.skel.rs"));
+//!
+//! let mut open = filter::OpenSkeleton::open(include_bytes!("../bpf/filter.o"))?;
+//! open.set_allowed_vlan(&100)?;
+//! let skel = open.load()?;
+//! let maps = skel.maps()?;
+//! maps.counters.update(&6, &filter::counter::default(), MapFlags::ANY)?;
+//! skel.progs()?.filter.attach_xdp(ifindex)?;
+//! ```
+//!
+//! C types keep their names. Structs get explicit padding fields, so they can
+//! be copied in and out of maps byte for byte, and implement [Pod]. Unions and
+//! floats, which [Pod] does not cover, become byte arrays, and bitfields
+//! become byte arrays spanning their bits.
+//!
+//! [Pod]: crate::Pod
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::global::{btf_kind, btf_vlen};
+use crate::{cstr_to_string, Error, Map, ObjectBuilder, Result};
+
+/// Generates the Rust module for a BPF object.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SkeletonGenerator {
+    name: String,
+}
+
+impl SkeletonGenerator {
+    /// `name` is the name of the generated module, and is also used as the
+    /// name of the object when it is opened.
+    #[must_use]
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        SkeletonGenerator {
+            name: ident(&name.into()),
+        }
+    }
+
+    /// Generate the module for the object in `obj_buf`.
+    pub fn generate(&self, obj_buf: &[u8]) -> Result<String> {
+        let obj = ObjectBuilder::new().name(&self.name).open_memory(obj_buf)?;
+        let btf = unsafe { libbpf_sys::bpf_object__btf(obj.obj) };
+        if btf.is_null() {
+            return Err(Error::InvalidArgument(
+                "object has no BTF, it must be compiled with -g",
+            ));
+        }
+        let mut types = TypeGen::new(btf);
+
+        let progs: Vec<String> = obj.programs().map(|prog| prog.name()).collect();
+        let mut maps = vec![];
+        let mut globals = vec![];
+        for map in obj.maps() {
+            if unsafe { libbpf_sys::bpf_map__is_internal(map.map) } {
+                if !map.name().ends_with(".kconfig") {
+                    globals.extend(unsafe { types.globals(&map) });
+                }
+                continue;
+            }
+            maps.push(unsafe { types.map_field(&map) });
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "// Generated by bpf::skel::SkeletonGenerator. Do not edit."
+        );
+        let _ = writeln!(out);
+        let _ = writeln!(out, "/// Skeleton of the `{}` BPF object.", self.name);
+        let _ = writeln!(out, "pub mod {} {{", self.name);
+        let _ = writeln!(
+            out,
+            "    #![allow(non_camel_case_types, non_snake_case, dead_code)]"
+        );
+        for def in &types.defs {
+            let _ = write!(out, "\n{def}");
+        }
+        self.write_open_skeleton(&mut out, &globals);
+        write_skeleton(&mut out, &progs, &maps, &globals);
+        let _ = writeln!(out, "}}");
+        Ok(out)
+    }
+
+    fn write_open_skeleton(&self, out: &mut String, globals: &[(String, String)]) {
+        let _ = write!(
+            out,
+            r#"
+    /// The object before it is loaded, to set global variables and configure
+    /// programs and maps.
+    pub struct OpenSkeleton {{
+        obj: bpf::Object,
+    }}
+
+    impl OpenSkeleton {{
+        /// Open the object the skeleton was generated from.
+        pub fn open(obj_buf: &[u8]) -> bpf::Result<Self> {{
+            let obj = bpf::ObjectBuilder::new()
+                .name("{name}")
+                .open_memory(obj_buf)?;
+            Ok(OpenSkeleton {{ obj }})
+        }}
+
+        /// Wrap an object opened with other options, which must be the object
+        /// the skeleton was generated from.
+        pub fn from_object(obj: bpf::Object) -> Self {{
+            OpenSkeleton {{ obj }}
+        }}
+
+        pub fn object_mut(&mut self) -> &mut bpf::Object {{
+            &mut self.obj
+        }}
+
+        pub fn load(mut self) -> bpf::Result<Skeleton> {{
+            self.obj.load()?;
+            Ok(Skeleton {{ obj: self.obj }})
+        }}
+"#,
+            name = self.name
+        );
+        for (name, ty) in globals {
+            let _ = write!(
+                out,
+                r#"
+        pub fn set_{name}(&mut self, value: &{ty}) -> bpf::Result<()> {{
+            self.obj.set_global("{name}", value)
+        }}
+"#
+            );
+        }
+        let _ = writeln!(out, "    }}");
+    }
+}
+
+/// A map of the skeleton, as the type of its field and the expression that
+/// wraps a [Map] in it.
+struct MapField {
+    name: String,
+    ty: String,
+    wrap: &'static str,
+}
+
+fn write_skeleton(
+    out: &mut String,
+    progs: &[String],
+    maps: &[MapField],
+    globals: &[(String, String)],
+) {
+    let _ = write!(
+        out,
+        r#"
+    /// The loaded object.
+    pub struct Skeleton {{
+        obj: bpf::Object,
+    }}
+
+    impl Skeleton {{
+        pub fn object(&self) -> &bpf::Object {{
+            &self.obj
+        }}
+
+        pub fn progs(&self) -> bpf::Result<Progs<'_>> {{
+            Ok(Progs {{
+"#
+    );
+    for prog in progs {
+        let _ = writeln!(
+            out,
+            "                {}: self.obj.find_program(\"{prog}\")?,",
+            ident(prog)
+        );
+    }
+    let _ = write!(
+        out,
+        r#"                _obj: std::marker::PhantomData,
+            }})
+        }}
+
+        pub fn maps(&self) -> bpf::Result<Maps<'_>> {{
+            Ok(Maps {{
+"#
+    );
+    for map in maps {
+        let _ = writeln!(
+            out,
+            "                {}: {}(self.obj.find_map(\"{}\")?)?,",
+            ident(&map.name),
+            map.wrap,
+            map.name
+        );
+    }
+    let _ = write!(
+        out,
+        r#"                _obj: std::marker::PhantomData,
+            }})
+        }}
+"#
+    );
+    for (name, ty) in globals {
+        let getter = match name.as_str() {
+            "object" | "progs" | "maps" => format!("{name}_global"),
+            name => ident(name),
+        };
+        let _ = write!(
+            out,
+            r#"
+        /// The current value of `{name}`.
+        pub fn {getter}(&self) -> bpf::Result<{ty}> {{
+            self.obj.global("{name}")
+        }}
+"#
+        );
+    }
+    let _ = write!(
+        out,
+        r#"    }}
+
+    pub struct Progs<'a> {{
+"#
+    );
+    for prog in progs {
+        let _ = writeln!(out, "        pub {}: bpf::Program<'a>,", ident(prog));
+    }
+    let _ = write!(
+        out,
+        r#"        _obj: std::marker::PhantomData<&'a bpf::Object>,
+    }}
+
+    pub struct Maps<'a> {{
+"#
+    );
+    for map in maps {
+        let _ = writeln!(out, "        pub {}: {},", ident(&map.name), map.ty);
+    }
+    let _ = writeln!(
+        out,
+        "        _obj: std::marker::PhantomData<&'a bpf::Object>,\n    }}"
+    );
+}
+
+/// Translates BTF types into Rust types, collecting the definitions of the
+/// structs it meets along the way.
+struct TypeGen {
+    btf: *const libbpf_sys::btf,
+    defs: Vec<String>,
+    /// Names of the types already defined, by BTF id.
+    names: HashMap<u32, String>,
+    used_names: HashSet<String>,
+}
+
+impl TypeGen {
+    #[must_use]
+    fn new(btf: *const libbpf_sys::btf) -> Self {
+        TypeGen {
+            btf,
+            defs: vec![],
+            names: HashMap::new(),
+            used_names: HashSet::new(),
+        }
+    }
+
+    /// The typed wrapper for a map, falling back to [Map] for map types
+    /// without one.
+    ///
+    /// # Safety
+    ///
+    /// `map` must belong to the object the BTF is from.
+    #[must_use]
+    unsafe fn map_field(&mut self, map: &Map) -> MapField {
+        let key = libbpf_sys::bpf_map__btf_key_type_id(map.map);
+        let value = libbpf_sys::bpf_map__btf_value_type_id(map.map);
+        let mut key = || self.type_or_bytes(key, map.key_size());
+        let (ty, wrap) = match map.map_type() {
+            libbpf_sys::BPF_MAP_TYPE_HASH | libbpf_sys::BPF_MAP_TYPE_LRU_HASH => {
+                let key = key();
+                let value = self.type_or_bytes(value, map.value_size());
+                (
+                    format!("bpf::HashMap<'a, {key}, {value}>"),
+                    "bpf::HashMap::new",
+                )
+            }
+            libbpf_sys::BPF_MAP_TYPE_PERCPU_HASH | libbpf_sys::BPF_MAP_TYPE_LRU_PERCPU_HASH => {
+                let key = key();
+                let value = self.type_or_bytes(value, map.value_size());
+                (
+                    format!("bpf::PerCpuHashMap<'a, {key}, {value}>"),
+                    "bpf::PerCpuHashMap::new",
+                )
+            }
+            libbpf_sys::BPF_MAP_TYPE_ARRAY => {
+                let value = self.type_or_bytes(value, map.value_size());
+                (format!("bpf::Array<'a, {value}>"), "bpf::Array::new")
+            }
+            libbpf_sys::BPF_MAP_TYPE_PERCPU_ARRAY => {
+                let value = self.type_or_bytes(value, map.value_size());
+                (
+                    format!("bpf::PerCpuArray<'a, {value}>"),
+                    "bpf::PerCpuArray::new",
+                )
+            }
+            libbpf_sys::BPF_MAP_TYPE_XSKMAP => ("bpf::XskMap<'a>".into(), "bpf::XskMap::new"),
+            libbpf_sys::BPF_MAP_TYPE_PROG_ARRAY => {
+                ("bpf::ProgArray<'a>".into(), "bpf::ProgArray::new")
+            }
+            libbpf_sys::BPF_MAP_TYPE_DEVMAP | libbpf_sys::BPF_MAP_TYPE_DEVMAP_HASH => {
+                ("bpf::DevMap<'a>".into(), "bpf::DevMap::new")
+            }
+            libbpf_sys::BPF_MAP_TYPE_CPUMAP => ("bpf::CpuMap<'a>".into(), "bpf::CpuMap::new"),
+            _ => ("bpf::Map<'a>".into(), "bpf::Result::Ok"),
+        };
+        MapField {
+            name: map.name(),
+            ty,
+            wrap,
+        }
+    }
+
+    /// Name and type of every variable in a global data map.
+    ///
+    /// # Safety
+    ///
+    /// `map` must belong to the object the BTF is from.
+    #[must_use]
+    unsafe fn globals(&mut self, map: &Map) -> Vec<(String, String)> {
+        let datasec = libbpf_sys::bpf_map__btf_value_type_id(map.map);
+        let sec = libbpf_sys::btf__type_by_id(self.btf, datasec);
+        if sec.is_null() || btf_kind(&*sec) != libbpf_sys::BTF_KIND_DATASEC {
+            return vec![];
+        }
+        let vars = std::slice::from_raw_parts(
+            sec.add(1) as *const libbpf_sys::btf_var_secinfo,
+            btf_vlen(&*sec),
+        );
+        vars.iter()
+            .filter_map(|secinfo| {
+                let var = libbpf_sys::btf__type_by_id(self.btf, secinfo.type_);
+                if var.is_null() || btf_kind(&*var) != libbpf_sys::BTF_KIND_VAR {
+                    return None;
+                }
+                let linkage = *(var.add(1) as *const u32);
+                if linkage == libbpf_sys::BTF_VAR_GLOBAL_EXTERN {
+                    return None;
+                }
+                let name = self.name(&*var);
+                let ty = self.type_or_bytes((*var).__bindgen_anon_1.type_, secinfo.size);
+                Some((name, ty))
+            })
+            .collect()
+    }
+
+    /// The Rust type of `id`, or a byte array of `size` if the BTF doesn't
+    /// say, as for maps declared with `key_size` and `value_size`.
+    #[must_use]
+    unsafe fn type_or_bytes(&mut self, id: u32, size: u32) -> String {
+        match id {
+            0 => format!("[u8; {size}]"),
+            id => self.rust_type(id, None),
+        }
+    }
+
+    /// The Rust type of `id`. An anonymous struct is named after `typedef`,
+    /// the typedef it was reached through, if there is one.
+    #[must_use]
+    unsafe fn rust_type(&mut self, id: u32, typedef: Option<&str>) -> String {
+        let t = libbpf_sys::btf__type_by_id(self.btf, id);
+        if id == 0 || t.is_null() {
+            return "[u8; 0]".into();
+        }
+        let t = &*t;
+        let size = t.__bindgen_anon_1.size;
+        let signed = |signed: bool| if signed { "i" } else { "u" };
+        match btf_kind(t) {
+            libbpf_sys::BTF_KIND_INT => {
+                let encoding = *((t as *const libbpf_sys::btf_type).add(1) as *const u32);
+                let is_signed = (encoding >> 24) & libbpf_sys::BTF_INT_SIGNED != 0;
+                int_type(signed(is_signed), size)
+            }
+            libbpf_sys::BTF_KIND_ENUM | libbpf_sys::BTF_KIND_ENUM64 => {
+                int_type(signed(t.info >> 31 != 0), size)
+            }
+            libbpf_sys::BTF_KIND_PTR => "u64".into(),
+            libbpf_sys::BTF_KIND_ARRAY => {
+                let array =
+                    &*((t as *const libbpf_sys::btf_type).add(1) as *const libbpf_sys::btf_array);
+                format!("[{}; {}]", self.rust_type(array.type_, None), array.nelems)
+            }
+            libbpf_sys::BTF_KIND_TYPEDEF => {
+                let name = self.name(t);
+                self.rust_type(t.__bindgen_anon_1.type_, Some(&name))
+            }
+            libbpf_sys::BTF_KIND_CONST
+            | libbpf_sys::BTF_KIND_VOLATILE
+            | libbpf_sys::BTF_KIND_RESTRICT
+            | libbpf_sys::BTF_KIND_TYPE_TAG => self.rust_type(t.__bindgen_anon_1.type_, typedef),
+            libbpf_sys::BTF_KIND_STRUCT => self.define_struct(id, t, typedef),
+            libbpf_sys::BTF_KIND_UNION => self.define_union(id, t, typedef),
+            _ => {
+                let size = libbpf_sys::btf__resolve_size(self.btf, id).max(0);
+                format!("[u8; {size}]")
+            }
+        }
+    }
+
+    #[must_use]
+    unsafe fn define_struct(
+        &mut self,
+        id: u32,
+        t: &libbpf_sys::btf_type,
+        typedef: Option<&str>,
+    ) -> String {
+        if let Some(name) = self.names.get(&id) {
+            return name.clone();
+        }
+        let name = self.type_name(id, t, typedef);
+
+        let members = std::slice::from_raw_parts(
+            (t as *const libbpf_sys::btf_type).add(1) as *const libbpf_sys::btf_member,
+            btf_vlen(t),
+        );
+        let kflag = t.info >> 31 != 0;
+        let mut fields = vec![];
+        let mut padding = 0;
+        let mut pad = |fields: &mut Vec<(String, String)>, len: usize| {
+            fields.push((format!("_pad{padding}"), format!("[u8; {len}]")));
+            padding += 1;
+        };
+        let mut cursor = 0;
+        // Bytes holding consecutive bitfields, as a start and end offset.
+        let mut bitfields: Option<(usize, usize)> = None;
+        let mut packed = false;
+        for (i, member) in members.iter().enumerate() {
+            let (bit_offset, bit_size) = match kflag {
+                true => (member.offset & 0xffffff, member.offset >> 24),
+                false => (member.offset, 0),
+            };
+            let offset = bit_offset as usize / 8;
+            if bit_size != 0 || bit_offset % 8 != 0 {
+                let bits = match bit_size {
+                    0 => libbpf_sys::btf__resolve_size(self.btf, member.type_).max(0) as u32 * 8,
+                    bits => bits,
+                };
+                let end = (bit_offset + bits).div_ceil(8) as usize;
+                bitfields = match bitfields {
+                    Some((start, prev_end)) => Some((start, prev_end.max(end))),
+                    None => Some((offset.max(cursor), end)),
+                };
+                continue;
+            }
+            if let Some((start, end)) = bitfields.take() {
+                if start > cursor {
+                    pad(&mut fields, start - cursor);
+                }
+                let end = end.min(offset);
+                fields.push((format!("_bitfield{i}"), format!("[u8; {}]", end - start)));
+                cursor = end;
+            }
+            if offset < cursor {
+                continue;
+            }
+            if offset > cursor {
+                pad(&mut fields, offset - cursor);
+            }
+            let align = libbpf_sys::btf__align_of(self.btf, member.type_).max(1) as usize;
+            packed |= !offset.is_multiple_of(align);
+            let field = match self.name_by_offset(member.name_off) {
+                field if field.is_empty() => format!("anon{i}"),
+                field => ident(&field),
+            };
+            fields.push((field, self.rust_type(member.type_, None)));
+            cursor = offset + libbpf_sys::btf__resolve_size(self.btf, member.type_).max(0) as usize;
+        }
+        let size = t.__bindgen_anon_1.size as usize;
+        if let Some((start, end)) = bitfields {
+            if start > cursor {
+                pad(&mut fields, start - cursor);
+            }
+            let end = end.min(size);
+            fields.push(("_bitfield".into(), format!("[u8; {}]", end - start)));
+            cursor = end;
+        }
+        if size > cursor {
+            pad(&mut fields, size - cursor);
+        }
+        let align = libbpf_sys::btf__align_of(self.btf, id).max(1) as usize;
+        packed |= !size.is_multiple_of(align);
+
+        let repr = if packed { "C, packed" } else { "C" };
+        let mut def = String::new();
+        let _ = writeln!(def, "    /// `struct {}`.", self.name(t));
+        let _ = writeln!(def, "    #[repr({repr})]");
+        let _ = writeln!(def, "    #[derive(Debug, Copy, Clone, PartialEq, Eq)]");
+        let _ = writeln!(def, "    pub struct {name} {{");
+        for (field, ty) in &fields {
+            let _ = writeln!(def, "        pub {field}: {ty},");
+        }
+        let _ = writeln!(def, "    }}");
+        let _ = writeln!(def);
+        let _ = writeln!(def, "    unsafe impl bpf::Pod for {name} {{}}");
+        let _ = writeln!(def);
+        let _ = writeln!(def, "    impl Default for {name} {{");
+        let _ = writeln!(def, "        fn default() -> Self {{");
+        let _ = writeln!(def, "            unsafe {{ std::mem::zeroed() }}");
+        let _ = writeln!(def, "        }}");
+        let _ = writeln!(def, "    }}");
+        self.defs.push(def);
+        name
+    }
+
+    #[must_use]
+    unsafe fn define_union(
+        &mut self,
+        id: u32,
+        t: &libbpf_sys::btf_type,
+        typedef: Option<&str>,
+    ) -> String {
+        if let Some(name) = self.names.get(&id) {
+            return name.clone();
+        }
+        let name = self.type_name(id, t, typedef);
+        self.defs.push(format!(
+            "    /// `union {}`, as its bytes.\n    pub type {name} = [u8; {}];\n",
+            self.name(t),
+            t.__bindgen_anon_1.size
+        ));
+        name
+    }
+
+    /// Reserve a unique Rust name for the type `id`.
+    #[must_use]
+    unsafe fn type_name(
+        &mut self,
+        id: u32,
+        t: &libbpf_sys::btf_type,
+        typedef: Option<&str>,
+    ) -> String {
+        let name = match (self.name(t), typedef) {
+            (name, _) if !name.is_empty() => ident(&name),
+            (_, Some(typedef)) => ident(typedef),
+            _ => format!("anon_{id}"),
+        };
+        let name = match self.used_names.contains(&name) {
+            true => format!("{name}_{id}"),
+            false => name,
+        };
+        self.used_names.insert(name.clone());
+        self.names.insert(id, name.clone());
+        name
+    }
+
+    #[must_use]
+    unsafe fn name(&self, t: &libbpf_sys::btf_type) -> String {
+        self.name_by_offset(t.name_off)
+    }
+
+    #[must_use]
+    unsafe fn name_by_offset(&self, offset: u32) -> String {
+        cstr_to_string(libbpf_sys::btf__name_by_offset(self.btf, offset))
+    }
+}
+
+#[must_use]
+fn int_type(sign: &str, size: u32) -> String {
+    match size {
+        1 | 2 | 4 | 8 | 16 => format!("{sign}{}", size * 8),
+        size => format!("[u8; {size}]"),
+    }
+}
+
+/// A Rust identifier for a C name, which may be a Rust keyword.
+#[must_use]
+fn ident(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.as_str() {
+        "" => "_".into(),
+        "self" | "Self" | "super" | "crate" | "_" => format!("{name}_"),
+        "as" | "async" | "await" | "box" | "break" | "const" | "continue" | "dyn" | "else"
+        | "enum" | "extern" | "false" | "fn" | "for" | "gen" | "if" | "impl" | "in" | "let"
+        | "loop" | "match" | "mod" | "move" | "mut" | "pub" | "ref" | "return" | "static"
+        | "struct" | "trait" | "true" | "try" | "type" | "unsafe" | "use" | "where" | "while"
+        | "yield" => format!("r#{name}"),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{name}"),
+        name => name.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_gets_explicit_padding() {
+        unsafe {
+            let btf = libbpf_sys::btf__new_empty();
+            let u8_ = libbpf_sys::btf__add_int(btf, c"unsigned char".as_ptr(), 1, 0);
+            let u64_ = libbpf_sys::btf__add_int(btf, c"long long".as_ptr(), 8, 1);
+            let u64_t = libbpf_sys::btf__add_typedef(btf, c"__s64".as_ptr(), u64_);
+            let event = libbpf_sys::btf__add_struct(btf, c"event".as_ptr(), 24);
+            libbpf_sys::btf__add_field(btf, c"proto".as_ptr(), u8_, 0, 0);
+            libbpf_sys::btf__add_field(btf, c"type".as_ptr(), u64_t, 64, 0);
+
+            let mut types = TypeGen::new(btf);
+            assert_eq!("event", types.rust_type(event as u32, None));
+            let def = &types.defs[0];
+            assert!(def.contains("#[repr(C)]"));
+            assert!(def.contains("pub proto: u8,\n        pub _pad0: [u8; 7],"));
+            assert!(def.contains("pub r#type: i64,\n        pub _pad1: [u8; 8],"));
+
+            libbpf_sys::btf__free(btf);
+        }
+    }
+
+    #[test]
+    fn test_anonymous_struct_is_named_after_typedef() {
+        unsafe {
+            let btf = libbpf_sys::btf__new_empty();
+            let int = libbpf_sys::btf__add_int(btf, c"int".as_ptr(), 4, 1);
+            let anon = libbpf_sys::btf__add_struct(btf, std::ptr::null(), 4);
+            libbpf_sys::btf__add_field(btf, c"a".as_ptr(), int, 0, 0);
+            let typedef = libbpf_sys::btf__add_typedef(btf, c"pair_t".as_ptr(), anon);
+            let array = libbpf_sys::btf__add_array(btf, int, typedef, 3);
+
+            let mut types = TypeGen::new(btf);
+            assert_eq!("[pair_t; 3]", types.rust_type(array as u32, None));
+            assert_eq!(1, types.defs.len());
+
+            libbpf_sys::btf__free(btf);
+        }
+    }
+
+    #[test]
+    fn test_ident_escapes_keywords() {
+        assert_eq!("r#type", ident("type"));
+        assert_eq!("self_", ident("self"));
+        assert_eq!("_4tuple", ident("4tuple"));
+        assert_eq!("rx_map", ident("rx_map"));
+    }
+}
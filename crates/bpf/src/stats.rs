@@ -0,0 +1,102 @@
+//! Counting how often programs run and how long they take. The kernel only
+//! keeps these counts while run-time stats are enabled, which costs a couple
+//! of clock reads per run, so they are enabled for as long as a [StatsGuard]
+//! is held.
+//!
+//! ```ignore
+//! let _stats = stats::enable_stats()?;
+//! let prog = obj.find_program("xsk_redirect")?;
+//! let before = prog.run_stats()?;
+//! std::thread::sleep(Duration::from_secs(1));
+//! let delta = prog.run_stats()?.since(&before);
+//! println!("{} runs, {:?} each", delta.run_count, delta.average());
+//! ```
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use crate::{obj_get_info, Errno, Error, Program, Result};
+
+/// Keeps run-time stats enabled until it is dropped. Stats stay enabled while
+/// any process holds a guard, or while the `kernel.bpf_stats_enabled` sysctl
+/// is set.
+#[derive(Debug)]
+pub struct StatsGuard {
+    _fd: OwnedFd,
+}
+
+/// Enable `BPF_STATS_RUN_TIME` for every program. Requires CAP_SYS_ADMIN.
+pub fn enable_stats() -> Result<StatsGuard> {
+    match unsafe { libbpf_sys::bpf_enable_stats(libbpf_sys::BPF_STATS_RUN_TIME) } {
+        ret if ret < 0 => Err(Error::EnableStats {
+            errno: Errno::from_ret(ret),
+        }),
+        fd => Ok(StatsGuard {
+            _fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        }),
+    }
+}
+
+/// How often a program has run and the time spent in it, counted while stats
+/// were enabled.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct RunStats {
+    pub run_count: u64,
+    pub run_time: Duration,
+}
+
+impl RunStats {
+    /// The runs between `earlier` and these stats.
+    #[must_use]
+    pub fn since(&self, earlier: &RunStats) -> RunStats {
+        RunStats {
+            run_count: self.run_count.saturating_sub(earlier.run_count),
+            run_time: self.run_time.saturating_sub(earlier.run_time),
+        }
+    }
+
+    /// Average time of a run, or `None` if there were no runs.
+    #[must_use]
+    pub fn average(&self) -> Option<Duration> {
+        match self.run_count {
+            0 => None,
+            count => Some(Duration::from_nanos(
+                (self.run_time.as_nanos() / count as u128) as u64,
+            )),
+        }
+    }
+}
+
+impl Program<'_> {
+    /// The program's run count and run time. The object must have been
+    /// loaded.
+    pub fn run_stats(&self) -> Result<RunStats> {
+        let info: libbpf_sys::bpf_prog_info = obj_get_info(self.fd()).map_err(Error::Errno)?;
+        Ok(RunStats {
+            run_count: info.run_cnt,
+            run_time: Duration::from_nanos(info.run_time_ns),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_stats_since() {
+        let before = RunStats {
+            run_count: 10,
+            run_time: Duration::from_nanos(1_000),
+        };
+        let after = RunStats {
+            run_count: 30,
+            run_time: Duration::from_nanos(3_000),
+        };
+
+        let delta = after.since(&before);
+
+        assert_eq!(20, delta.run_count);
+        assert_eq!(Some(Duration::from_nanos(100)), delta.average());
+        assert_eq!(None, before.since(&after).average());
+    }
+}
@@ -0,0 +1,377 @@
+//! Attaching `SEC("tc")` programs to the clsact ingress and egress hooks,
+//! which unlike XDP also see the packets an interface sends.
+//!
+//! ```ignore
+//! let prog = obj.find_program("mark_egress")?;
+//! let link = prog.attach_tc(ifindex, TcHook::Egress)?;
+//! ```
+//!
+//! [Program::attach_tc] uses a tcx link on kernels that have them (6.6 and
+//! later), and a `cls_bpf` filter on the clsact qdisc otherwise.
+//! [Program::attach_tc_with] always uses a filter, with control over its
+//! handle and priority. Either way the program is detached when the
+//! [TcLink] is dropped.
+use std::fmt;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use crate::{Errno, Error, Program, Result};
+
+/// Which clsact hook a TC program is attached to.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TcHook {
+    Ingress,
+    Egress,
+}
+
+impl TcHook {
+    #[must_use]
+    fn attach_point(self) -> libbpf_sys::bpf_tc_attach_point {
+        match self {
+            TcHook::Ingress => libbpf_sys::BPF_TC_INGRESS,
+            TcHook::Egress => libbpf_sys::BPF_TC_EGRESS,
+        }
+    }
+
+    #[must_use]
+    fn tcx_attach_type(self) -> libbpf_sys::bpf_attach_type {
+        match self {
+            TcHook::Ingress => libbpf_sys::BPF_TCX_INGRESS,
+            TcHook::Egress => libbpf_sys::BPF_TCX_EGRESS,
+        }
+    }
+}
+
+impl fmt::Display for TcHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcHook::Ingress => write!(f, "ingress"),
+            TcHook::Egress => write!(f, "egress"),
+        }
+    }
+}
+
+/// Options for attaching a program as a `cls_bpf` filter. The kernel picks
+/// the handle and priority that are left at zero.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct TcAttachOptions {
+    handle: u32,
+    priority: u32,
+    replace: bool,
+}
+
+impl TcAttachOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[must_use]
+    pub fn handle(mut self, handle: u32) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Filters run in order of priority, lowest first. Must fit in a `u16`.
+    #[must_use]
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Replace the filter with the same handle and priority instead of
+    /// failing with `EEXIST` (`BPF_TC_F_REPLACE`).
+    #[must_use]
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+}
+
+/// A program attached to a clsact hook. It is detached when dropped, or
+/// explicitly with [TcLink::detach] to observe errors. A tcx link that has
+/// been pinned stays attached after it is dropped, until the pin is removed.
+/// The clsact qdisc is left in place, since other filters may be using it.
+#[derive(Debug)]
+#[must_use = "the program is detached when the link is dropped"]
+pub struct TcLink {
+    ifindex: u32,
+    hook: TcHook,
+    attachment: TcAttachment,
+}
+
+#[derive(Debug)]
+enum TcAttachment {
+    Link(OwnedFd),
+    /// The filter is removed by handle and priority, so the id of the program
+    /// is kept to only remove it if it is still this program.
+    Filter {
+        handle: u32,
+        priority: u32,
+        prog_id: u32,
+    },
+    Detached,
+}
+
+impl TcLink {
+    pub(crate) fn from_link_fd(fd: OwnedFd, ifindex: u32, hook: TcHook) -> Self {
+        TcLink {
+            ifindex,
+            hook,
+            attachment: TcAttachment::Link(fd),
+        }
+    }
+
+    #[must_use]
+    pub fn ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    #[must_use]
+    pub fn hook(&self) -> TcHook {
+        self.hook
+    }
+
+    /// File descriptor of the tcx link, if the program was attached with one.
+    #[must_use]
+    pub fn link_fd(&self) -> Option<i32> {
+        match &self.attachment {
+            TcAttachment::Link(fd) => Some(fd.as_raw_fd()),
+            _ => None,
+        }
+    }
+
+    /// Handle and priority of the filter, if the program was attached as one.
+    #[must_use]
+    pub fn filter(&self) -> Option<(u32, u32)> {
+        match self.attachment {
+            TcAttachment::Filter {
+                handle, priority, ..
+            } => Some((handle, priority)),
+            _ => None,
+        }
+    }
+
+    /// Detach the program from the hook, even if the link is pinned. A filter
+    /// is only removed if it still runs this program.
+    pub fn detach(mut self) -> Result<()> {
+        let ret = match std::mem::replace(&mut self.attachment, TcAttachment::Detached) {
+            TcAttachment::Link(fd) => unsafe { libbpf_sys::bpf_link_detach(fd.as_raw_fd()) },
+            TcAttachment::Filter {
+                handle,
+                priority,
+                prog_id,
+            } => tc_detach(self.ifindex, self.hook, handle, priority, prog_id),
+            TcAttachment::Detached => 0,
+        };
+
+        match ret {
+            ret if ret < 0 => Err(Error::Detach {
+                errno: Errno::from_ret(ret),
+                ifindex: self.ifindex,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ids of the programs attached to the hook with tcx links, in the order
+    /// they run. Filters are not included, see [TcLink::query_filter].
+    pub fn query(ifindex: u32, hook: TcHook) -> Result<Vec<u32>> {
+        let mut opts = libbpf_sys::bpf_prog_query_opts {
+            sz: size_of::<libbpf_sys::bpf_prog_query_opts>() as _,
+            ..Default::default()
+        };
+        let query = |opts: &mut libbpf_sys::bpf_prog_query_opts| match unsafe {
+            libbpf_sys::bpf_prog_query_opts(ifindex as i32, hook.tcx_attach_type(), opts)
+        } {
+            ret if ret < 0 => Err(Error::Errno(Errno::from_ret(ret))),
+            _ => Ok(unsafe { opts.__bindgen_anon_1.count }),
+        };
+
+        // The first query only counts the programs.
+        let count = query(&mut opts)?;
+        let mut prog_ids = vec![0u32; count as usize];
+        opts.prog_ids = prog_ids.as_mut_ptr();
+        let count = query(&mut opts)?;
+        prog_ids.truncate(count as usize);
+        Ok(prog_ids)
+    }
+
+    /// Id of the program in the filter with `handle` and `priority` on the
+    /// hook, or `None` if there is no such filter.
+    pub fn query_filter(
+        ifindex: u32,
+        hook: TcHook,
+        handle: u32,
+        priority: u32,
+    ) -> Result<Option<u32>> {
+        if handle == 0 || priority == 0 || priority > u16::MAX as u32 {
+            return Err(Error::InvalidArgument(
+                "filter handle must be set and priority must be from 1 to 65535",
+            ));
+        }
+        match tc_query(ifindex, hook, handle, priority) {
+            Ok(prog_id) => Ok(Some(prog_id)),
+            Err(errno) if filter_not_found(errno) => Ok(None),
+            Err(errno) => Err(Error::Errno(errno)),
+        }
+    }
+}
+
+impl Drop for TcLink {
+    fn drop(&mut self) {
+        // Closing a link fd detaches it unless it is pinned.
+        if let TcAttachment::Filter {
+            handle,
+            priority,
+            prog_id,
+        } = self.attachment
+        {
+            tc_detach(self.ifindex, self.hook, handle, priority, prog_id);
+        }
+    }
+}
+
+impl Program<'_> {
+    /// Attach the program to a clsact hook with a tcx link, after any
+    /// programs already attached. Falls back to a filter with
+    /// [Program::attach_tc_with] on kernels without tcx.
+    pub fn attach_tc(&self, ifindex: u32, hook: TcHook) -> Result<TcLink> {
+        let opts = libbpf_sys::bpf_link_create_opts {
+            sz: size_of::<libbpf_sys::bpf_link_create_opts>() as _,
+            ..Default::default()
+        };
+        match unsafe {
+            libbpf_sys::bpf_link_create(self.fd(), ifindex as i32, hook.tcx_attach_type(), &opts)
+        } {
+            // Kernels without tcx reject the attach type.
+            ret if ret == -libc::EINVAL => {
+                self.attach_tc_with(ifindex, hook, TcAttachOptions::new())
+            }
+            ret if ret < 0 => Err(self.tc_attach_error(Errno::from_ret(ret), ifindex, hook)),
+            fd => Ok(TcLink::from_link_fd(
+                unsafe { OwnedFd::from_raw_fd(fd) },
+                ifindex,
+                hook,
+            )),
+        }
+    }
+
+    /// Attach the program as a `cls_bpf` filter in direct-action mode,
+    /// creating the clsact qdisc if the interface does not have one.
+    pub fn attach_tc_with(
+        &self,
+        ifindex: u32,
+        hook: TcHook,
+        opts: TcAttachOptions,
+    ) -> Result<TcLink> {
+        let mut tc_hook = tc_hook(ifindex, hook);
+        match unsafe { libbpf_sys::bpf_tc_hook_create(&mut tc_hook) } {
+            ret if ret < 0 && ret != -libc::EEXIST => {
+                return Err(self.tc_attach_error(Errno::from_ret(ret), ifindex, hook))
+            }
+            _ => {}
+        }
+
+        let mut tc_opts = libbpf_sys::bpf_tc_opts {
+            sz: size_of::<libbpf_sys::bpf_tc_opts>() as _,
+            prog_fd: self.fd(),
+            flags: match opts.replace {
+                true => libbpf_sys::BPF_TC_F_REPLACE,
+                false => 0,
+            },
+            handle: opts.handle,
+            priority: opts.priority,
+            ..Default::default()
+        };
+        match unsafe { libbpf_sys::bpf_tc_attach(&tc_hook, &mut tc_opts) } {
+            ret if ret < 0 => Err(self.tc_attach_error(Errno::from_ret(ret), ifindex, hook)),
+            _ => Ok(TcLink {
+                ifindex,
+                hook,
+                attachment: TcAttachment::Filter {
+                    handle: tc_opts.handle,
+                    priority: tc_opts.priority,
+                    prog_id: tc_opts.prog_id,
+                },
+            }),
+        }
+    }
+
+    #[must_use]
+    fn tc_attach_error(&self, errno: Errno, ifindex: u32, hook: TcHook) -> Error {
+        Error::TcAttach {
+            errno,
+            program: self.name(),
+            ifindex,
+            hook,
+        }
+    }
+}
+
+#[must_use]
+fn tc_hook(ifindex: u32, hook: TcHook) -> libbpf_sys::bpf_tc_hook {
+    libbpf_sys::bpf_tc_hook {
+        sz: size_of::<libbpf_sys::bpf_tc_hook>() as _,
+        ifindex: ifindex as i32,
+        attach_point: hook.attach_point(),
+        ..Default::default()
+    }
+}
+
+fn tc_query(
+    ifindex: u32,
+    hook: TcHook,
+    handle: u32,
+    priority: u32,
+) -> std::result::Result<u32, Errno> {
+    let tc_hook = tc_hook(ifindex, hook);
+    let mut opts = libbpf_sys::bpf_tc_opts {
+        sz: size_of::<libbpf_sys::bpf_tc_opts>() as _,
+        handle,
+        priority,
+        ..Default::default()
+    };
+    match unsafe { libbpf_sys::bpf_tc_query(&tc_hook, &mut opts) } {
+        ret if ret < 0 => Err(Errno::from_ret(ret)),
+        _ => Ok(opts.prog_id),
+    }
+}
+
+/// The kernel removes the filter chain along with its last filter, and then
+/// fails to find the chain with `EINVAL` rather than the filter with `ENOENT`.
+#[must_use]
+fn filter_not_found(errno: Errno) -> bool {
+    matches!(errno.0, libc::ENOENT | libc::EINVAL)
+}
+
+/// Remove the filter if it still runs the program with id `prog_id`.
+fn tc_detach(ifindex: u32, hook: TcHook, handle: u32, priority: u32, prog_id: u32) -> i32 {
+    match tc_query(ifindex, hook, handle, priority) {
+        Ok(id) if id == prog_id => {}
+        // Already removed, or replaced by another program.
+        Ok(_) => return 0,
+        Err(errno) if filter_not_found(errno) => return 0,
+        Err(errno) => return -errno.0,
+    }
+    let tc_hook = tc_hook(ifindex, hook);
+    let opts = libbpf_sys::bpf_tc_opts {
+        sz: size_of::<libbpf_sys::bpf_tc_opts>() as _,
+        handle,
+        priority,
+        ..Default::default()
+    };
+    unsafe { libbpf_sys::bpf_tc_detach(&tc_hook, &opts) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hooks_map_to_attach_points() {
+        assert_eq!(libbpf_sys::BPF_TC_INGRESS, TcHook::Ingress.attach_point());
+        assert_eq!(libbpf_sys::BPF_TCX_EGRESS, TcHook::Egress.tcx_attach_type());
+        assert_eq!("egress", TcHook::Egress.to_string());
+    }
+}
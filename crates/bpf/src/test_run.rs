@@ -0,0 +1,223 @@
+//! Running a loaded XDP program against a crafted packet with
+//! `BPF_PROG_TEST_RUN`, without attaching it to an interface.
+//!
+//! ```ignore
+//! let result = prog.test_run(&TestRunOptions::new(&packet).rx_queue_index(1))?;
+//! assert_eq!(XdpAction::Redirect, result.action);
+//! ```
+use std::mem::size_of;
+use std::ptr::{null, null_mut};
+use std::time::Duration;
+
+use crate::{Errno, Error, Program, Result};
+
+/// Room left for a program that grows the packet with `bpf_xdp_adjust_tail`.
+const DATA_OUT_TAILROOM: usize = 4096;
+
+/// The action an XDP program returned.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum XdpAction {
+    Aborted,
+    Drop,
+    Pass,
+    Tx,
+    Redirect,
+    Unknown(u32),
+}
+
+impl From<u32> for XdpAction {
+    fn from(action: u32) -> Self {
+        match action {
+            libbpf_sys::XDP_ABORTED => XdpAction::Aborted,
+            libbpf_sys::XDP_DROP => XdpAction::Drop,
+            libbpf_sys::XDP_PASS => XdpAction::Pass,
+            libbpf_sys::XDP_TX => XdpAction::Tx,
+            libbpf_sys::XDP_REDIRECT => XdpAction::Redirect,
+            action => XdpAction::Unknown(action),
+        }
+    }
+}
+
+impl From<XdpAction> for u32 {
+    fn from(action: XdpAction) -> Self {
+        match action {
+            XdpAction::Aborted => libbpf_sys::XDP_ABORTED,
+            XdpAction::Drop => libbpf_sys::XDP_DROP,
+            XdpAction::Pass => libbpf_sys::XDP_PASS,
+            XdpAction::Tx => libbpf_sys::XDP_TX,
+            XdpAction::Redirect => libbpf_sys::XDP_REDIRECT,
+            XdpAction::Unknown(action) => action,
+        }
+    }
+}
+
+/// Input for [Program::test_run].
+#[derive(Debug, Default, Clone)]
+pub struct TestRunOptions<'a> {
+    data: &'a [u8],
+    ingress_ifindex: Option<u32>,
+    rx_queue_index: Option<u32>,
+    repeat: u32,
+    flags: u32,
+    batch_size: u32,
+}
+
+impl<'a> TestRunOptions<'a> {
+    #[must_use]
+    pub fn new(data: &'a [u8]) -> Self {
+        TestRunOptions {
+            data,
+            ..Default::default()
+        }
+    }
+
+    /// Interface the packet appears to arrive on. The kernel checks that it
+    /// exists, and it must be set for `rx_queue_index`.
+    #[must_use]
+    pub fn ingress_ifindex(mut self, ifindex: u32) -> Self {
+        self.ingress_ifindex = Some(ifindex);
+        self
+    }
+
+    #[must_use]
+    pub fn rx_queue_index(mut self, queue: u32) -> Self {
+        self.rx_queue_index = Some(queue);
+        self
+    }
+
+    /// Run the program this many times, reporting the average duration.
+    #[must_use]
+    pub fn repeat(mut self, repeat: u32) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Raw `BPF_F_TEST_*` flags.
+    #[must_use]
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Really transmit or redirect the packet according to the action, for
+    /// example into an `AF_XDP` socket (`BPF_F_TEST_XDP_LIVE_FRAMES`). The
+    /// kernel reports neither the action nor the output packet in this mode.
+    #[must_use]
+    pub fn live_frames(mut self, live_frames: bool) -> Self {
+        match live_frames {
+            true => self.flags |= libbpf_sys::BPF_F_TEST_XDP_LIVE_FRAMES,
+            false => self.flags &= !libbpf_sys::BPF_F_TEST_XDP_LIVE_FRAMES,
+        }
+        self
+    }
+
+    /// Number of frames processed at once in live frames mode.
+    #[must_use]
+    pub fn batch_size(mut self, batch_size: u32) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    fn is_live(&self) -> bool {
+        self.flags & libbpf_sys::BPF_F_TEST_XDP_LIVE_FRAMES != 0
+    }
+
+    /// The `xdp_md` passed in, if any field of it was set. The kernel requires
+    /// `data_end` to be the packet length.
+    #[must_use]
+    fn ctx(&self) -> Option<libbpf_sys::xdp_md> {
+        if self.ingress_ifindex.is_none() && self.rx_queue_index.is_none() {
+            return None;
+        }
+        Some(libbpf_sys::xdp_md {
+            data_end: self.data.len() as u32,
+            ingress_ifindex: self.ingress_ifindex.unwrap_or(0),
+            rx_queue_index: self.rx_queue_index.unwrap_or(0),
+            ..Default::default()
+        })
+    }
+}
+
+/// Output of [Program::test_run].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TestRunResult {
+    /// The action returned, always [XdpAction::Aborted] in live frames mode.
+    pub action: XdpAction,
+    /// The packet after the program ran, empty in live frames mode.
+    pub data: Vec<u8>,
+    /// Average time taken by one run.
+    pub duration: Duration,
+}
+
+impl Program<'_> {
+    /// Run the loaded program on a packet with `BPF_PROG_TEST_RUN`.
+    pub fn test_run(&self, opts: &TestRunOptions) -> Result<TestRunResult> {
+        let ctx = opts.ctx();
+        let mut data_out = match opts.is_live() {
+            true => vec![],
+            false => vec![0; opts.data.len() + DATA_OUT_TAILROOM],
+        };
+
+        let mut run_opts = libbpf_sys::bpf_test_run_opts {
+            sz: size_of::<libbpf_sys::bpf_test_run_opts>() as _,
+            data_in: opts.data.as_ptr() as _,
+            data_size_in: opts.data.len() as u32,
+            data_out: match data_out.is_empty() {
+                true => null_mut(),
+                false => data_out.as_mut_ptr() as _,
+            },
+            data_size_out: data_out.len() as u32,
+            ctx_in: ctx.as_ref().map_or(null(), |ctx| ctx as *const _ as _),
+            ctx_size_in: ctx.map_or(0, |_| size_of::<libbpf_sys::xdp_md>() as u32),
+            repeat: opts.repeat as i32,
+            flags: opts.flags,
+            batch_size: opts.batch_size,
+            ..Default::default()
+        };
+
+        match unsafe { libbpf_sys::bpf_prog_test_run_opts(self.fd(), &mut run_opts) } {
+            ret if ret < 0 => Err(Error::TestRun {
+                errno: Errno::from_ret(ret),
+                program: self.name(),
+            }),
+            _ => {
+                data_out.truncate(run_opts.data_size_out as usize);
+                Ok(TestRunResult {
+                    action: run_opts.retval.into(),
+                    data: data_out,
+                    duration: Duration::from_nanos(run_opts.duration as u64),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ctx_only_passed_when_set() {
+        let packet = [0u8; 60];
+
+        assert!(TestRunOptions::new(&packet).ctx().is_none());
+
+        let ctx = TestRunOptions::new(&packet)
+            .rx_queue_index(3)
+            .ctx()
+            .unwrap();
+        assert_eq!(60, ctx.data_end);
+        assert_eq!(3, ctx.rx_queue_index);
+    }
+
+    #[test]
+    fn test_live_frames_toggles_flag() {
+        let opts = TestRunOptions::new(&[]).live_frames(true);
+        assert!(opts.is_live());
+
+        let opts = opts.live_frames(false);
+        assert_eq!(0, opts.flags);
+    }
+}
@@ -0,0 +1,41 @@
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+/**
+ * Map of queue index to AF_XDP sockets, filled in by `XdpChannel::register`.
+ * `ForwardProgram::load` resizes it to the number of rx queues.
+ */
+struct {
+  __uint(type, BPF_MAP_TYPE_XSKMAP);
+  __type(key, __u32);
+  __type(value, __u32);
+  __uint(max_entries, 64);
+} xsks_map SEC(".maps");
+
+/**
+ * Map of ingress ifindex to the interface its packets are forwarded to,
+ * filled in by `ForwardProgram::forward`. The value is a
+ * `struct bpf_devmap_val`, so an entry can run a program before sending.
+ */
+struct {
+  __uint(type, BPF_MAP_TYPE_DEVMAP_HASH);
+  __type(key, __u32);
+  __uint(value_size, sizeof(struct bpf_devmap_val));
+  __uint(max_entries, 64);
+} tx_ports SEC(".maps");
+
+/**
+ * Redirect each packet to the socket bound to the queue it arrived on, like
+ * `xsk_redirect`. Packets for queues without a socket are sent out of the
+ * interface that `tx_ports` maps the ingress interface to, and go to the
+ * network stack when there is none.
+ */
+SEC("xdp")
+int xsk_forward(struct xdp_md* ctx) {
+  if (bpf_redirect_map(&xsks_map, ctx->rx_queue_index, XDP_PASS) == XDP_REDIRECT)
+    return XDP_REDIRECT;
+
+  return bpf_redirect_map(&tx_ports, ctx->ingress_ifindex, XDP_PASS);
+}
+
+char _license[] SEC("license") = "GPL";
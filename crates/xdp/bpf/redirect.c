@@ -0,0 +1,25 @@
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+/**
+ * Map of queue index to AF_XDP sockets, filled in by `XdpChannel::register`.
+ * `DefaultProgram::load` resizes it to the number of rx queues.
+ */
+struct {
+  __uint(type, BPF_MAP_TYPE_XSKMAP);
+  __type(key, __u32);
+  __type(value, __u32);
+  __uint(max_entries, 64);
+} xsks_map SEC(".maps");
+
+/**
+ * Redirect each packet to the socket bound to the queue it arrived on. The
+ * low bits of the flags are the action taken when there is no socket in the
+ * map for the queue, so packets for other queues go to the network stack.
+ */
+SEC("xdp")
+int xsk_redirect(struct xdp_md* ctx) {
+  return bpf_redirect_map(&xsks_map, ctx->rx_queue_index, XDP_PASS);
+}
+
+char _license[] SEC("license") = "GPL";
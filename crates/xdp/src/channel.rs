@@ -1,14 +1,26 @@
+use std::marker::PhantomData;
 use std::slice::IterMut;
 
-use crate::socket::{OwnedUmem, SharedUmem};
+use bpf::XdpMode;
+
+use crate::profile::Profiler;
+use crate::program::{DefaultProgram, ForwardProgram};
+use crate::socket::{BindMode, Bound, OwnedUmem, SharedUmem, Unbound};
+use crate::sys::if_indextoname;
 use crate::sys::if_nametoindex;
+use crate::sys::netdev;
+use crate::sys::netlink;
+use crate::sys::preflight::Preflight;
 use crate::umem::Umem;
 use crate::Result;
 use crate::{error::Error, socket::XdpSocket};
 
-pub struct XdpChannel {
-    owner: XdpSocket<OwnedUmem>,
-    peers: Vec<XdpSocket<SharedUmem>>,
+pub struct XdpChannel<S = Unbound> {
+    // Declared first so the map entries are removed before the sockets close.
+    registration: Option<Registration>,
+    program: Option<ChannelProgram>,
+    owner: XdpSocket<OwnedUmem, S>,
+    peers: Vec<XdpSocket<SharedUmem, S>>,
 }
 
 impl XdpChannel {
@@ -18,6 +30,13 @@ impl XdpChannel {
         sock_config: SockConfig,
         device_config: DeviceConfig,
     ) -> Result<Self> {
+        Preflight::new()
+            .ifname(&device_config.ifname)
+            .queues(device_config.queues.iter().copied())
+            .memlock(umem_config.frame_count as u64 * umem_config.frame_size as u64)
+            .raise_memlock(umem_config.raise_memlock)
+            .run()?;
+
         let ifindex = if_nametoindex(device_config.ifname)?;
         let mut queues = device_config.queues.iter();
 
@@ -34,6 +53,7 @@ impl XdpChannel {
 
         let owner = XdpSocket::builder()
             .owned_umem(umem)
+            .mode(resolve_mode(sock_config.mode, ifindex)?)
             .rx_size(sock_config.rx_size)
             .tx_size(sock_config.tx_size)
             .queue(owner_queue)
@@ -52,7 +72,12 @@ impl XdpChannel {
             })
             .collect::<Result<Vec<_>>>()?;
 
-        Ok(XdpChannel { owner, peers })
+        Ok(XdpChannel {
+            registration: None,
+            program: None,
+            owner,
+            peers,
+        })
     }
 
     #[must_use]
@@ -60,22 +85,166 @@ impl XdpChannel {
         XdpChannelBuilder::new()
     }
 
+    /// Bind every socket in the channel. The owner is bound first, because the
+    /// kernel requires the UMEM owner to be bound before it can be shared.
+    pub fn bind(self) -> Result<XdpChannel<Bound>> {
+        let owner = self.owner.bind()?;
+        let peers = self
+            .peers
+            .into_iter()
+            .map(XdpSocket::<SharedUmem>::bind)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(XdpChannel {
+            registration: self.registration,
+            program: self.program,
+            owner,
+            peers,
+        })
+    }
+}
+
+impl XdpChannel<Bound> {
+    /// Insert every socket into an `XSKMAP` at the index of the queue it is
+    /// bound to, which is where a program redirecting by `rx_queue_index`
+    /// looks for it. The entries are removed when the channel is dropped, or
+    /// when another map is registered. The channel keeps its own handle to
+    /// the map, so the map's object may be dropped first.
+    pub fn register(&mut self, map: &bpf::XskMap) -> Result<()> {
+        let mut registration = Registration {
+            map: map.try_clone()?,
+            queues: vec![],
+        };
+        self.registration = None;
+
+        let socks = std::iter::once((self.owner.queue(), self.owner.fd()))
+            .chain(self.peers.iter().map(|sock| (sock.queue(), sock.fd())));
+        for (queue, fd) in socks {
+            registration.map.update(queue, fd as i32)?;
+            registration.queues.push(queue);
+        }
+
+        self.registration = Some(registration);
+        Ok(())
+    }
+
+    /// Load the default program, register every socket in its map and attach
+    /// it to the interface. The program is detached when the channel is
+    /// dropped.
+    pub fn attach_default_program(&mut self, mode: XdpMode) -> Result<()> {
+        let mut program = DefaultProgram::load(self.queue_count()?)?;
+        self.register(&program.xsk_map()?)?;
+        program.attach(self.owner.ifindex(), mode)?;
+        self.program = Some(ChannelProgram::Default(program));
+        Ok(())
+    }
+
+    /// Load the forwarding program, register every socket in its map and
+    /// attach it to the interface. Packets that arrive on queues without a
+    /// socket are sent out of the interface `peer` instead of going to the
+    /// network stack. The program is detached when the channel is dropped.
+    pub fn attach_forward_program(&mut self, peer: &str, mode: XdpMode) -> Result<()> {
+        let peer = if_nametoindex(peer.to_owned())?;
+        let mut program = ForwardProgram::load(self.queue_count()?)?;
+        self.register(&program.xsk_map()?)?;
+        program.forward(self.owner.ifindex(), peer)?;
+        program.attach(self.owner.ifindex(), mode)?;
+        self.program = Some(ChannelProgram::Forward(program));
+        Ok(())
+    }
+
+    /// Size of the `XSKMAP` of a program loaded by the channel. Every bound
+    /// queue must fit, even if the driver under-reports them.
+    fn queue_count(&self) -> Result<u32> {
+        let ifname = if_indextoname(self.owner.ifindex())?;
+        let highest_queue = self
+            .peers
+            .iter()
+            .map(|sock| sock.queue())
+            .fold(self.owner.queue(), u32::max);
+        Ok(netdev::rx_queue_count(&ifname)?.max(highest_queue + 1))
+    }
+
+    /// Profile the program the channel loaded, counting its run time against
+    /// the packets passed to [Profiler::record]. A caller that attaches its
+    /// own program profiles it with [Profiler::new] instead.
+    pub fn profiler(&self) -> Result<Profiler> {
+        match &self.program {
+            Some(ChannelProgram::Default(program)) => Profiler::new(&program.program()?),
+            Some(ChannelProgram::Forward(program)) => Profiler::new(&program.program()?),
+            None => Err(Error::NotFound("the channel has not loaded a program")),
+        }
+    }
+
+    /// The map the sockets are registered in, if any.
+    #[must_use]
+    pub fn xsk_map(&self) -> Option<&bpf::XskMap<'static>> {
+        self.registration
+            .as_ref()
+            .map(|registration| &registration.map)
+    }
+}
+
+impl<S> XdpChannel<S> {
     #[must_use]
     pub fn socks(
         &mut self,
     ) -> (
-        &mut XdpSocket<OwnedUmem>,
-        IterMut<'_, XdpSocket<SharedUmem>>,
+        &mut XdpSocket<OwnedUmem, S>,
+        IterMut<'_, XdpSocket<SharedUmem, S>>,
     ) {
         (&mut self.owner, self.peers.iter_mut())
     }
 }
 
+/// A program loaded and attached by the channel, held until it is dropped.
+enum ChannelProgram {
+    Default(DefaultProgram),
+    Forward(ForwardProgram),
+}
+
+/// Sockets inserted into an `XSKMAP`, removed again on drop.
+struct Registration {
+    map: bpf::XskMap<'static>,
+    queues: Vec<u32>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        for queue in &self.queues {
+            // The kernel also drops the entry when the socket is closed, so
+            // failing here leaves nothing behind.
+            let _ = self.map.delete(*queue);
+        }
+    }
+}
+
+/// Pick the bind mode from the features the device advertises, so a device
+/// without zero-copy support is rejected before any sockets are bound. Kernels
+/// that can't report features keep the requested mode.
+fn resolve_mode(mode: BindMode, ifindex: u32) -> Result<BindMode> {
+    let Ok(features) = netlink::device_features(ifindex) else {
+        return Ok(mode);
+    };
+    match mode {
+        BindMode::Auto if features.xdp.zero_copy() => Ok(BindMode::ZeroCopy),
+        BindMode::Auto => Ok(BindMode::Copy),
+        BindMode::ZeroCopy if !features.xdp.zero_copy() => {
+            Err(Error::ZeroCopyUnsupported { ifindex })
+        }
+        mode => Ok(mode),
+    }
+}
+
 #[derive(Default, Debug, PartialEq, Eq)]
-pub struct XdpChannelBuilder {
+pub struct XdpChannelBuilder<S = Unbound> {
     umem: Option<UmemConfig>,
     socks: Option<SockConfig>,
     netdev: Option<DeviceConfig>,
+    prefill: bool,
+    own_program: bool,
+    attach_mode: XdpMode,
+    forward_to: Option<String>,
+    state: PhantomData<S>,
 }
 
 impl XdpChannelBuilder {
@@ -84,6 +253,83 @@ impl XdpChannelBuilder {
         Default::default()
     }
 
+    /// Bind every socket as part of [XdpChannelBuilder::build].
+    #[must_use]
+    pub fn bind(self) -> XdpChannelBuilder<Bound> {
+        XdpChannelBuilder {
+            umem: self.umem,
+            socks: self.socks,
+            netdev: self.netdev,
+            prefill: self.prefill,
+            own_program: self.own_program,
+            attach_mode: self.attach_mode,
+            forward_to: self.forward_to,
+            state: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> Result<XdpChannel> {
+        let (umem, socks, netdev) = self.configs()?;
+        XdpChannel::new(umem, socks, netdev)
+    }
+}
+
+impl XdpChannelBuilder<Bound> {
+    /// Enqueue every UMEM frame on the fill ring before binding, so the kernel
+    /// can start delivering packets immediately.
+    #[must_use]
+    pub fn prefill(mut self, prefill: bool) -> Self {
+        self.prefill = prefill;
+        self
+    }
+
+    /// Skip the default program, because the caller attaches its own and
+    /// registers the sockets with [XdpChannel::register].
+    #[must_use]
+    pub fn own_program(mut self, own_program: bool) -> Self {
+        self.own_program = own_program;
+        self
+    }
+
+    /// Mode to attach the default program in.
+    #[must_use]
+    pub fn attach_mode(mut self, mode: XdpMode) -> Self {
+        self.attach_mode = mode;
+        self
+    }
+
+    /// Attach the forwarding program instead of the default program, sending
+    /// packets on queues without a socket out of the interface `peer`. See
+    /// [XdpChannel::attach_forward_program].
+    #[must_use]
+    pub fn forward_to(mut self, peer: impl Into<String>) -> Self {
+        self.forward_to = Some(peer.into());
+        self
+    }
+
+    /// Create and bind the channel, then load and attach the default program,
+    /// or the forwarding program if [XdpChannelBuilder::forward_to] is set,
+    /// unless [XdpChannelBuilder::own_program] is set.
+    pub fn build(mut self) -> Result<XdpChannel<Bound>> {
+        let (prefill, own_program, attach_mode) =
+            (self.prefill, self.own_program, self.attach_mode);
+        let forward_to = self.forward_to.take();
+        let (umem, socks, netdev) = self.configs()?;
+        let mut chan = XdpChannel::new(umem, socks, netdev)?;
+        if prefill {
+            chan.owner.umem().prefill();
+        }
+        let mut chan = chan.bind()?;
+        match forward_to {
+            _ if own_program => {}
+            Some(peer) => chan.attach_forward_program(&peer, attach_mode)?,
+            None => chan.attach_default_program(attach_mode)?,
+        }
+        Ok(chan)
+    }
+}
+
+impl<S> XdpChannelBuilder<S> {
     #[must_use]
     pub fn umem(mut self, umem: UmemConfig) -> Self {
         self.umem = Some(umem);
@@ -102,8 +348,7 @@ impl XdpChannelBuilder {
         self
     }
 
-    #[must_use]
-    pub fn build(self) -> Result<XdpChannel> {
+    fn configs(self) -> Result<(UmemConfig, SockConfig, DeviceConfig)> {
         let umem = self
             .umem
             .ok_or(Error::NotFound("umem config is required"))?;
@@ -113,7 +358,7 @@ impl XdpChannelBuilder {
         let netdev = self
             .netdev
             .ok_or(Error::NotFound("netdev config is required"))?;
-        XdpChannel::new(umem, socks, netdev)
+        Ok((umem, socks, netdev))
     }
 }
 
@@ -122,6 +367,7 @@ pub struct UmemConfig {
     frame_count: u32,
     frame_size: u32,
     frame_headroom: u32,
+    raise_memlock: bool,
 }
 
 impl UmemConfig {
@@ -137,6 +383,7 @@ impl Default for UmemConfig {
             frame_count: 4096,
             frame_size: 4096,
             frame_headroom: 0,
+            raise_memlock: false,
         }
     }
 }
@@ -170,6 +417,13 @@ impl UmemConfigBuilder {
         self
     }
 
+    /// Raise `RLIMIT_MEMLOCK` if it is too low to register the UMEM.
+    #[must_use]
+    pub fn raise_memlock(mut self, raise_memlock: bool) -> Self {
+        self.cfg.raise_memlock = raise_memlock;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> UmemConfig {
         self.cfg
@@ -180,6 +434,7 @@ impl UmemConfigBuilder {
 pub struct SockConfig {
     rx_size: usize,
     tx_size: usize,
+    mode: BindMode,
 }
 
 impl SockConfig {
@@ -194,6 +449,7 @@ impl Default for SockConfig {
         Self {
             rx_size: 2048,
             tx_size: 2048,
+            mode: BindMode::Auto,
         }
     }
 }
@@ -221,6 +477,14 @@ impl SockConfigBuilder {
         self
     }
 
+    /// With [BindMode::Auto], the channel asks the device whether it supports
+    /// zero-copy and binds accordingly.
+    #[must_use]
+    pub fn mode(mut self, mode: BindMode) -> Self {
+        self.cfg.mode = mode;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> SockConfig {
         self.cfg
@@ -244,6 +508,7 @@ impl DeviceConfig {
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct DeviceConfigBuilder {
     queues: Option<Vec<u32>>,
+    all_queues: bool,
     ifname: Option<String>,
 }
 
@@ -262,6 +527,14 @@ impl DeviceConfigBuilder {
         self
     }
 
+    /// Use every rx queue on the interface, as reported by the driver when
+    /// the config is built. Overrides [DeviceConfigBuilder::queues].
+    #[must_use]
+    pub fn all_queues(mut self) -> Self {
+        self.all_queues = true;
+        self
+    }
+
     #[must_use]
     pub fn ifname(mut self, name: &str) -> Self {
         self.ifname = Some(name.to_owned());
@@ -270,8 +543,12 @@ impl DeviceConfigBuilder {
 
     #[must_use]
     pub fn build(self) -> Result<DeviceConfig> {
-        let queues = self.queues.ok_or(Error::NotFound("queues are required"))?;
         let ifname = self.ifname.ok_or(Error::NotFound("ifname is required"))?;
+        let queues = if self.all_queues {
+            (0..netdev::rx_queue_count(&ifname)?).collect()
+        } else {
+            self.queues.ok_or(Error::NotFound("queues are required"))?
+        };
         Ok(DeviceConfig { queues, ifname })
     }
 }
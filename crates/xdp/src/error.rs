@@ -1,25 +1,54 @@
+use crate::ring::RingKind;
+use crate::sys::preflight::Diagnostics;
+use crate::sys::socket::sockopt_name;
+use bpf::error::Hint;
+
+pub use bpf::Errno;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("{0}")]
-    IoError(std::io::Error),
+    Io(#[from] std::io::Error),
     #[error("{0}")]
-    BpfError(bpf::Error),
-    #[error("failed to create socket: {0}")]
-    Socket(i32),
-    #[error("failed to bind: {0}")]
-    Bind(i32),
-    #[error("failed to bpf: {0}")]
-    Bpf(i32),
-    #[error("failed to if_nametoindex: {0}")]
-    IfNameToIndex(i32),
-    #[error("failed to mmap: {0}")]
-    Mmap(i32),
+    Bpf(#[from] bpf::Error),
+    #[error("failed to create socket: {errno}{}", Hint(hint::socket(*.errno)))]
+    Socket { errno: Errno },
+    #[error("failed to bind to ifindex {ifindex} queue {queue}: {errno}{}", Hint(hint::bind(*.errno)))]
+    Bind {
+        errno: Errno,
+        ifindex: u32,
+        queue: u32,
+    },
+    #[error("failed to find interface \"{ifname}\": {errno}")]
+    IfNameToIndex { errno: Errno, ifname: String },
+    #[error("failed to find interface with ifindex {ifindex}: {errno}")]
+    IfIndexToName { errno: Errno, ifindex: u32 },
+    #[error("failed to mmap {len} bytes: {errno}{}", Hint(hint::mmap(*.errno)))]
+    Mmap { errno: Errno, len: usize },
     #[error("failed to munmap: {0}")]
-    Munmap(i32),
-    #[error("failed to setsockopt: {0}")]
-    SetSockOpt(i32),
-    #[error("failed to getsockopt: {0}")]
-    GetSockOpt(i32),
+    Munmap(Errno),
+    #[error("failed to setsockopt {}: {errno}{}", sockopt_name(*.opt), Hint(hint::sockopt(*.opt, *.errno)))]
+    SetSockOpt { errno: Errno, opt: u32 },
+    #[error("failed to getsockopt {}: {errno}", sockopt_name(*.opt))]
+    GetSockOpt { errno: Errno, opt: u32 },
+    #[error("failed to set up {ring} ring: {source}")]
+    Ring {
+        ring: RingKind,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("failed to {cmd} on \"{ifname}\": {errno}{}", Hint(hint::ethtool(*.errno)))]
+    Ethtool {
+        errno: Errno,
+        ifname: String,
+        cmd: &'static str,
+    },
+    #[error("failed netlink {op}: {errno}{}", Hint(hint::netlink(*.errno)))]
+    Netlink { errno: Errno, op: &'static str },
+    #[error("ifindex {ifindex} does not support zero-copy AF_XDP sockets")]
+    ZeroCopyUnsupported { ifindex: u32 },
+    #[error("preflight checks failed:{0}")]
+    Preflight(Diagnostics),
     #[error("invalid argument: {0}")]
     InvalidArgument(&'static str),
     #[error("EFAULT: {0}")]
@@ -27,3 +56,200 @@ pub enum Error {
     #[error("{0}")]
     NotFound(&'static str),
 }
+
+impl Error {
+    /// The errno that caused this error, if there is one.
+    #[must_use]
+    pub fn errno(&self) -> Option<Errno> {
+        match self {
+            Error::Io(err) => err.raw_os_error().map(Errno),
+            Error::Bpf(err) => err.errno(),
+            Error::Socket { errno }
+            | Error::Bind { errno, .. }
+            | Error::IfNameToIndex { errno, .. }
+            | Error::IfIndexToName { errno, .. }
+            | Error::Mmap { errno, .. }
+            | Error::Munmap(errno)
+            | Error::SetSockOpt { errno, .. }
+            | Error::GetSockOpt { errno, .. }
+            | Error::Ethtool { errno, .. }
+            | Error::Netlink { errno, .. } => Some(*errno),
+            Error::Ring { source, .. } => source.errno(),
+            Error::ZeroCopyUnsupported { .. } => Some(Errno(libc::EOPNOTSUPP)),
+            Error::Preflight(_)
+            | Error::InvalidArgument(_)
+            | Error::Efault(_)
+            | Error::NotFound(_) => None,
+        }
+    }
+
+    /// A suggestion for how to fix common failures, if one is known.
+    #[must_use]
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Bpf(err) => err.hint(),
+            Error::Socket { errno } => hint::socket(*errno),
+            Error::Bind { errno, .. } => hint::bind(*errno),
+            Error::Mmap { errno, .. } => hint::mmap(*errno),
+            Error::SetSockOpt { errno, opt } => hint::sockopt(*opt, *errno),
+            Error::Ethtool { errno, .. } => hint::ethtool(*errno),
+            Error::Netlink { errno, .. } => hint::netlink(*errno),
+            Error::Ring { source, .. } => source.hint(),
+            _ => None,
+        }
+    }
+
+    /// Attach the ring that was being set up when this error occurred.
+    #[must_use]
+    pub(crate) fn in_ring(self, ring: RingKind) -> Self {
+        Error::Ring {
+            ring,
+            source: Box::new(self),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => match err.errno() {
+                Some(errno) => std::io::Error::new(std::io::Error::from(errno).kind(), err),
+                None => std::io::Error::new(std::io::ErrorKind::InvalidInput, err),
+            },
+        }
+    }
+}
+
+/// Suggestions for the failures people most commonly run into, keyed by the
+/// operation that failed.
+pub mod hint {
+    use super::Errno;
+
+    pub use bpf::error::hint::MEMLOCK;
+
+    #[must_use]
+    pub fn socket(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EPERM => Some("AF_XDP sockets require CAP_NET_RAW"),
+            libc::EAFNOSUPPORT => Some("the kernel was built without CONFIG_XDP_SOCKETS"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn bind(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EBUSY => Some("another AF_XDP socket is already bound to this queue"),
+            libc::EOPNOTSUPP => {
+                Some("the driver does not support zero-copy on this queue, try copy mode")
+            }
+            libc::EINVAL => Some("the queue id may not exist on this interface"),
+            libc::ENODEV | libc::ENXIO => Some("the interface does not exist"),
+            libc::EBADF => Some("the socket owning the shared UMEM must be bound first"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn mmap(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::ENOMEM | libc::EAGAIN => Some(MEMLOCK),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn sockopt(opt: u32, errno: Errno) -> Option<&'static str> {
+        match (opt, errno.0) {
+            (xdp_sys::XDP_UMEM_REG, libc::ENOMEM | libc::EPERM | libc::ENOBUFS) => Some(MEMLOCK),
+            (xdp_sys::XDP_UMEM_REG, libc::EBUSY) => {
+                Some("a UMEM is already registered on this socket")
+            }
+            (xdp_sys::XDP_UMEM_REG, libc::EINVAL) => Some(
+                "the frame size must be a power of two from 2048 to the page size, and the \
+                 headroom must leave 256 bytes of the frame for XDP",
+            ),
+            (
+                xdp_sys::XDP_RX_RING
+                | xdp_sys::XDP_TX_RING
+                | xdp_sys::XDP_UMEM_FILL_RING
+                | xdp_sys::XDP_UMEM_COMPLETION_RING,
+                libc::EINVAL,
+            ) => Some("ring sizes must be a power of two"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn ethtool(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::EOPNOTSUPP => Some("the driver does not implement this ethtool command"),
+            libc::EPERM => Some("changing channels requires CAP_NET_ADMIN"),
+            libc::ENODEV => Some("the interface does not exist"),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn netlink(errno: Errno) -> Option<&'static str> {
+        match errno.0 {
+            libc::ENOENT => Some("the netdev netlink family needs Linux 6.3 or newer"),
+            libc::ENODEV => Some("the interface does not exist"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_error_display_includes_context_and_hint() {
+        let err = Error::Bind {
+            errno: Errno(libc::EBUSY),
+            ifindex: 2,
+            queue: 1,
+        };
+
+        assert_eq!(
+            "failed to bind to ifindex 2 queue 1: Device or resource busy (errno 16) \
+             (hint: another AF_XDP socket is already bound to this queue)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_ring_error_forwards_errno_and_hint() {
+        let err = Error::Mmap {
+            errno: Errno(libc::ENOMEM),
+            len: 4096,
+        }
+        .in_ring(RingKind::Fill);
+
+        assert_eq!(Some(Errno(libc::ENOMEM)), err.errno());
+        assert_eq!(Some(hint::MEMLOCK), err.hint());
+        assert!(err.to_string().starts_with("failed to set up fill ring"));
+    }
+
+    #[test]
+    fn test_sockopt_hint_depends_on_option() {
+        let ring = hint::sockopt(xdp_sys::XDP_UMEM_FILL_RING, Errno(libc::EINVAL));
+        let umem = hint::sockopt(xdp_sys::XDP_UMEM_REG, Errno(libc::EINVAL));
+
+        assert_eq!(Some("ring sizes must be a power of two"), ring);
+        assert!(umem.unwrap().starts_with("the frame size"));
+        assert_eq!(
+            None,
+            hint::sockopt(xdp_sys::XDP_STATISTICS, Errno(libc::EINVAL))
+        );
+    }
+
+    #[test]
+    fn test_bpf_error_converts_into_error() {
+        let err: Error = bpf::Error::MapNotFound("xsks_map".into()).into();
+
+        assert_eq!(Some(Errno(libc::ENOENT)), err.errno());
+    }
+}
@@ -6,6 +6,8 @@
 pub mod channel;
 pub mod constants;
 pub mod error;
+pub mod profile;
+pub mod program;
 pub mod ring;
 pub mod socket;
 pub mod sys;
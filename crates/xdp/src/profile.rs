@@ -0,0 +1,153 @@
+//! Measuring where the time goes: in the XDP program that redirects packets,
+//! and in userspace receiving them from the socket. The XDP program's run time
+//! comes from the kernel's run-time stats, which are enabled while a
+//! [Profiler] is alive.
+//!
+//! ```ignore
+//! let mut profiler = chan.profiler()?;
+//! loop {
+//!     let mut packets = 0;
+//!     while let Some(desc) = rx.dequeue() {
+//!         packets += 1;
+//!         fr.enqueue(desc.addr);
+//!     }
+//!     profiler.record(packets);
+//!     if profiler.elapsed() >= Duration::from_secs(1) {
+//!         println!("{}", profiler.sample()?);
+//!     }
+//! }
+//! ```
+//!
+//! The program runs for every packet on the interface's queues, including
+//! those it passes to the network stack, so its runs can outnumber the
+//! packets received in userspace.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use bpf::{RunStats, StatsGuard};
+
+use crate::Result;
+
+/// Counts packets received in userspace against the runs of an XDP program.
+pub struct Profiler {
+    prog: bpf::Program<'static>,
+    last: RunStats,
+    last_at: Instant,
+    packets: u64,
+    _stats: StatsGuard,
+}
+
+impl Profiler {
+    /// Enable run-time stats and start profiling `prog`, which must have been
+    /// loaded. Requires CAP_SYS_ADMIN.
+    pub fn new(prog: &bpf::Program) -> Result<Self> {
+        let stats = bpf::enable_stats()?;
+        let prog = prog.try_clone()?;
+        let last = prog.run_stats()?;
+        Ok(Profiler {
+            prog,
+            last,
+            last_at: Instant::now(),
+            packets: 0,
+            _stats: stats,
+        })
+    }
+
+    /// Count packets received in userspace.
+    pub fn record(&mut self, packets: usize) {
+        self.packets += packets as u64;
+    }
+
+    /// Time since the profiler was created or last sampled.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.last_at.elapsed()
+    }
+
+    /// The packets and program runs since the profiler was created or last
+    /// sampled, starting the next sample.
+    pub fn sample(&mut self) -> Result<Sample> {
+        let now = Instant::now();
+        let stats = self.prog.run_stats()?;
+        let sample = Sample {
+            elapsed: now - self.last_at,
+            packets: self.packets,
+            xdp: stats.since(&self.last),
+        };
+        self.last = stats;
+        self.last_at = now;
+        self.packets = 0;
+        Ok(sample)
+    }
+}
+
+/// What happened over one sampling period of a [Profiler].
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Sample {
+    pub elapsed: Duration,
+    /// Packets received in userspace.
+    pub packets: u64,
+    /// Runs of the XDP program and the time spent in them.
+    pub xdp: RunStats,
+}
+
+impl Sample {
+    /// Packets received in userspace per second.
+    #[must_use]
+    pub fn packets_per_sec(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.packets as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Average nanoseconds spent in a run of the XDP program, if it ran. The
+    /// program runs once per packet arriving on the interface, not only for
+    /// those received in userspace.
+    #[must_use]
+    pub fn xdp_ns_per_run(&self) -> Option<u64> {
+        self.xdp.average().map(|average| average.as_nanos() as u64)
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0} pkt/s in userspace", self.packets_per_sec())?;
+        match self.xdp_ns_per_run() {
+            Some(ns) => write!(
+                f,
+                ", XDP program {ns} ns/run over {} runs",
+                self.xdp.run_count
+            ),
+            None => write!(f, ", XDP program did not run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_display() {
+        let sample = Sample {
+            elapsed: Duration::from_millis(500),
+            packets: 1_000,
+            xdp: RunStats {
+                run_count: 1_200,
+                run_time: Duration::from_nanos(60_000),
+            },
+        };
+
+        assert_eq!(2_000.0, sample.packets_per_sec());
+        assert_eq!(Some(50), sample.xdp_ns_per_run());
+        assert_eq!(
+            "2000 pkt/s in userspace, XDP program 50 ns/run over 1200 runs",
+            sample.to_string()
+        );
+        assert_eq!(
+            "0 pkt/s in userspace, XDP program did not run",
+            Sample::default().to_string()
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! The default XDP program, equivalent to libxdp's. It redirects each packet
+//! to the `AF_XDP` socket bound to the queue it arrived on, and passes it to
+//! the network stack when there is none. The object is built from
+//! `bpf/redirect.c` and embedded in the crate, so using it needs no clang.
+//!
+//! ```ignore
+//! let mut program = DefaultProgram::load(netdev::rx_queue_count("eth0")?)?;
+//! chan.register(&program.xsk_map()?)?;
+//! program.attach(ifindex, XdpMode::Auto)?;
+//! ```
+//!
+//! The forwarding program, built from `bpf/forward.c`, redirects to the
+//! sockets the same way, but sends the packets of queues without a socket out
+//! of another interface instead of passing them.
+//!
+//! ```ignore
+//! let mut program = ForwardProgram::load(netdev::rx_queue_count("eth0")?)?;
+//! chan.register(&program.xsk_map()?)?;
+//! program.forward(ifindex, peer_ifindex)?;
+//! program.attach(ifindex, XdpMode::Auto)?;
+//! ```
+use bpf::{DevMap, LinkedProgram, Object, Program, XdpAttachOptions, XdpMode, XskMap};
+
+use crate::Result;
+
+/// The compiled BPF object of the default program.
+pub const DEFAULT_PROGRAM: &[u8] = include_bytes!("../bpf/redirect.o");
+pub const PROGRAM_NAME: &str = "xsk_redirect";
+pub const XSKS_MAP_NAME: &str = "xsks_map";
+
+/// The compiled BPF object of the forwarding program.
+pub const FORWARD_PROGRAM: &[u8] = include_bytes!("../bpf/forward.o");
+pub const FORWARD_PROGRAM_NAME: &str = "xsk_forward";
+pub const TX_PORTS_MAP_NAME: &str = "tx_ports";
+
+/// The loaded default program. It is detached and unloaded when dropped.
+pub struct DefaultProgram(RedirectProgram);
+
+impl DefaultProgram {
+    /// Load the program with room in its map for `queue_count` queues, which
+    /// should be the number of rx queues on the interface.
+    pub fn load(queue_count: u32) -> Result<Self> {
+        RedirectProgram::load(DEFAULT_PROGRAM, PROGRAM_NAME, queue_count).map(DefaultProgram)
+    }
+
+    /// The map of queue index to socket that the program redirects into.
+    pub fn xsk_map(&self) -> Result<XskMap<'_>> {
+        self.0.xsk_map()
+    }
+
+    /// The loaded program, e.g. to profile it with
+    /// [Profiler](crate::profile::Profiler).
+    pub fn program(&self) -> Result<Program<'_>> {
+        self.0.program()
+    }
+
+    /// Attach the program to the interface. [XdpMode::Auto] attaches with a
+    /// `bpf_link`, any other mode through netlink.
+    pub fn attach(&mut self, ifindex: u32, mode: XdpMode) -> Result<()> {
+        self.0.attach(ifindex, mode)
+    }
+
+    #[must_use]
+    pub fn link(&self) -> Option<&LinkedProgram> {
+        self.0.link.as_ref()
+    }
+}
+
+/// The loaded forwarding program. It is detached and unloaded when dropped.
+pub struct ForwardProgram(RedirectProgram);
+
+impl ForwardProgram {
+    /// Load the program with room in its map for `queue_count` queues, which
+    /// should be the number of rx queues on the interface.
+    pub fn load(queue_count: u32) -> Result<Self> {
+        RedirectProgram::load(FORWARD_PROGRAM, FORWARD_PROGRAM_NAME, queue_count)
+            .map(ForwardProgram)
+    }
+
+    /// The map of queue index to socket that the program redirects into.
+    pub fn xsk_map(&self) -> Result<XskMap<'_>> {
+        self.0.xsk_map()
+    }
+
+    /// The map of ingress ifindex to the interface its packets are sent out
+    /// of. An entry can also run a program loaded with `SEC("xdp/devmap")` on
+    /// each packet before it is sent.
+    pub fn tx_ports(&self) -> Result<DevMap<'_>> {
+        Ok(DevMap::new(self.0.obj.find_map(TX_PORTS_MAP_NAME)?)?)
+    }
+
+    /// Send packets that arrive on `from` and are not redirected to a socket
+    /// out of `to`. In native mode, the driver of `to` must support XDP
+    /// transmit, which for a veth means its peer must have an XDP program
+    /// attached.
+    pub fn forward(&self, from: u32, to: u32) -> Result<()> {
+        Ok(self.tx_ports()?.update(from, to, None)?)
+    }
+
+    /// The loaded program, e.g. to profile it with
+    /// [Profiler](crate::profile::Profiler).
+    pub fn program(&self) -> Result<Program<'_>> {
+        self.0.program()
+    }
+
+    /// Attach the program to the interface. [XdpMode::Auto] attaches with a
+    /// `bpf_link`, any other mode through netlink.
+    pub fn attach(&mut self, ifindex: u32, mode: XdpMode) -> Result<()> {
+        self.0.attach(ifindex, mode)
+    }
+
+    #[must_use]
+    pub fn link(&self) -> Option<&LinkedProgram> {
+        self.0.link.as_ref()
+    }
+}
+
+/// A loaded object whose program redirects to the sockets in its
+/// `XSKMAP`, shared by the embedded programs.
+struct RedirectProgram {
+    // Declared first so the program is detached before the object is closed.
+    link: Option<LinkedProgram>,
+    obj: Object,
+    name: &'static str,
+}
+
+impl RedirectProgram {
+    fn load(obj_buf: &[u8], name: &'static str, queue_count: u32) -> Result<Self> {
+        let mut obj = Object::create(obj_buf)?;
+        obj.find_map_mut(XSKS_MAP_NAME)?
+            .set_max_entries(queue_count)?;
+        obj.load()?;
+        Ok(RedirectProgram {
+            link: None,
+            obj,
+            name,
+        })
+    }
+
+    fn xsk_map(&self) -> Result<XskMap<'_>> {
+        Ok(XskMap::new(self.obj.find_map(XSKS_MAP_NAME)?)?)
+    }
+
+    fn program(&self) -> Result<Program<'_>> {
+        Ok(self.obj.find_program(self.name)?)
+    }
+
+    fn attach(&mut self, ifindex: u32, mode: XdpMode) -> Result<()> {
+        let prog = self.program()?;
+        let link = match mode {
+            XdpMode::Auto => prog.attach_xdp(ifindex)?,
+            mode => prog.attach_xdp_with(ifindex, XdpAttachOptions::new().mode(mode))?,
+        };
+        self.link = Some(link);
+        Ok(())
+    }
+}
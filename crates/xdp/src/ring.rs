@@ -4,6 +4,7 @@ use crate::sys::{
     socket::Socket,
 };
 use crate::Result;
+use std::fmt;
 use std::mem::size_of;
 
 pub type FillRing = RingBuffer<u64>;
@@ -12,6 +13,26 @@ pub type CompRing = RingBuffer<u64>;
 pub type RxRing = RingBuffer<xdp_sys::xdp_desc>;
 pub type TxRing = RingBuffer<xdp_sys::xdp_desc>;
 
+/// Identifies one of the four rings shared with the kernel.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum RingKind {
+    Rx,
+    Tx,
+    Fill,
+    Completion,
+}
+
+impl fmt::Display for RingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RingKind::Rx => write!(f, "rx"),
+            RingKind::Tx => write!(f, "tx"),
+            RingKind::Fill => write!(f, "fill"),
+            RingKind::Completion => write!(f, "completion"),
+        }
+    }
+}
+
 pub(crate) fn new_rx_ring<'a>(
     sock: &Socket,
     offsets: &xdp_sys::xdp_mmap_offsets,
@@ -1,25 +1,64 @@
+use std::marker::PhantomData;
+
 use crate::error::Error;
-use crate::ring::{new_rx_ring, new_tx_ring, RxRing, TxRing};
+use crate::ring::{new_rx_ring, new_tx_ring, RingKind, RxRing, TxRing};
 use crate::sys::socket::XdpMmapOffsets;
 use crate::Result;
 use crate::{sys::socket::Socket, umem::Umem};
 
+/// An `AF_XDP` socket. The `S` type parameter tracks whether the socket has
+/// been bound to a device queue yet, so the RX and TX rings can only be reached
+/// once the kernel is actually producing and consuming them.
 #[derive(Debug)]
-pub struct XdpSocket<U> {
+pub struct XdpSocket<U, S = Unbound> {
     sock: Socket,
     umem_ref: U,
     rx: RxRing,
     tx: TxRing,
     ifindex: u32,
     queue: u32,
+    state: PhantomData<S>,
+}
+
+unsafe impl<U: Send, S> Send for XdpSocket<U, S> {}
+unsafe impl<U: Sync, S> Sync for XdpSocket<U, S> {}
+
+/// Marker for a socket that has been created but not yet bound.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Unbound;
+
+/// Marker for a socket that has been bound to an interface queue.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Bound;
+
+/// How packets are moved between the driver and the UMEM.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum BindMode {
+    /// Let the kernel use zero-copy if the driver supports it, and fall back
+    /// to copy mode otherwise.
+    #[default]
+    Auto,
+    /// Fail to bind unless the driver supports zero-copy (`XDP_ZEROCOPY`).
+    ZeroCopy,
+    /// Always copy packets into the UMEM (`XDP_COPY`).
+    Copy,
 }
 
-unsafe impl<U: Send> Send for XdpSocket<U> {}
-unsafe impl<U: Sync> Sync for XdpSocket<U> {}
+impl BindMode {
+    #[must_use]
+    fn flags(self) -> u16 {
+        match self {
+            BindMode::Auto => 0,
+            BindMode::ZeroCopy => xdp_sys::XDP_ZEROCOPY as u16,
+            BindMode::Copy => xdp_sys::XDP_COPY as u16,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct OwnedUmem {
     umem: Umem,
+    mode: BindMode,
 }
 
 #[derive(Debug)]
@@ -38,8 +77,8 @@ impl XdpSocket<OwnedUmem> {
     ) -> Result<XdpSocket<OwnedUmem>> {
         let sock = umem_ref.umem.sock;
         let offsets = sock.get_opt::<XdpMmapOffsets>()?;
-        let rx = new_rx_ring(&sock, &offsets, rx_size)?;
-        let tx = new_tx_ring(&sock, &offsets, tx_size)?;
+        let rx = new_rx_ring(&sock, &offsets, rx_size).map_err(|err| err.in_ring(RingKind::Rx))?;
+        let tx = new_tx_ring(&sock, &offsets, tx_size).map_err(|err| err.in_ring(RingKind::Tx))?;
         Ok(XdpSocket {
             sock,
             umem_ref,
@@ -47,24 +86,38 @@ impl XdpSocket<OwnedUmem> {
             tx,
             ifindex,
             queue,
+            state: PhantomData,
         })
     }
 
-    #[inline]
+    /// Bind the socket to its interface queue. This consumes the socket, so it
+    /// cannot be bound twice.
     #[must_use]
-    pub fn umem(&mut self) -> &mut Umem {
-        &mut self.umem_ref.umem
+    pub fn bind(self) -> Result<XdpSocket<OwnedUmem, Bound>> {
+        self.sock
+            .bind(&xdp_sys::sockaddr_xdp {
+                sxdp_family: libc::PF_XDP as u16,
+                sxdp_flags: self.umem_ref.mode.flags(),
+                sxdp_ifindex: self.ifindex,
+                sxdp_queue_id: self.queue,
+                sxdp_shared_umem_fd: 0,
+            })
+            .map_err(|errno| Error::Bind {
+                errno,
+                ifindex: self.ifindex,
+                queue: self.queue,
+            })?;
+        Ok(self.into_state())
     }
+}
 
+impl<S> XdpSocket<OwnedUmem, S> {
+    /// The UMEM is accessible in every state, because the fill ring should be
+    /// populated before the socket is bound.
+    #[inline]
     #[must_use]
-    pub fn bind(&self) -> Result<()> {
-        self.sock.bind(&xdp_sys::sockaddr_xdp {
-            sxdp_family: libc::PF_XDP as u16,
-            sxdp_flags: 0,
-            sxdp_ifindex: self.ifindex,
-            sxdp_queue_id: self.queue,
-            sxdp_shared_umem_fd: 0,
-        })
+    pub fn umem(&mut self) -> &mut Umem {
+        &mut self.umem_ref.umem
     }
 }
 
@@ -79,8 +132,8 @@ impl XdpSocket<SharedUmem> {
     ) -> Result<XdpSocket<SharedUmem>> {
         let sock = Socket::create(libc::AF_XDP, libc::SOCK_RAW, 0)?;
         let offsets = sock.get_opt::<XdpMmapOffsets>()?;
-        let rx = new_rx_ring(&sock, &offsets, rx_size)?;
-        let tx = new_tx_ring(&sock, &offsets, tx_size)?;
+        let rx = new_rx_ring(&sock, &offsets, rx_size).map_err(|err| err.in_ring(RingKind::Rx))?;
+        let tx = new_tx_ring(&sock, &offsets, tx_size).map_err(|err| err.in_ring(RingKind::Tx))?;
         Ok(XdpSocket {
             sock,
             umem_ref,
@@ -88,18 +141,28 @@ impl XdpSocket<SharedUmem> {
             tx,
             ifindex,
             queue,
+            state: PhantomData,
         })
     }
 
+    /// Bind the socket to its interface queue, sharing the UMEM of the owner
+    /// socket. The owner must already be bound.
     #[must_use]
-    pub fn bind(&self) -> Result<()> {
-        self.sock.bind(&xdp_sys::sockaddr_xdp {
-            sxdp_family: libc::PF_XDP as u16,
-            sxdp_flags: xdp_sys::XDP_SHARED_UMEM as u16,
-            sxdp_ifindex: self.ifindex,
-            sxdp_queue_id: self.queue,
-            sxdp_shared_umem_fd: self.umem_ref.sock.fd as u32,
-        })
+    pub fn bind(self) -> Result<XdpSocket<SharedUmem, Bound>> {
+        self.sock
+            .bind(&xdp_sys::sockaddr_xdp {
+                sxdp_family: libc::PF_XDP as u16,
+                sxdp_flags: xdp_sys::XDP_SHARED_UMEM as u16,
+                sxdp_ifindex: self.ifindex,
+                sxdp_queue_id: self.queue,
+                sxdp_shared_umem_fd: self.umem_ref.sock.fd as u32,
+            })
+            .map_err(|errno| Error::Bind {
+                errno,
+                ifindex: self.ifindex,
+                queue: self.queue,
+            })?;
+        Ok(self.into_state())
     }
 }
 
@@ -108,11 +171,39 @@ impl<U> XdpSocket<U> {
     pub fn builder() -> XdpSocketBuilder<U> {
         XdpSocketBuilder::new()
     }
+}
 
+impl<U> XdpSocket<U, Bound> {
     #[must_use]
     pub fn rings(&mut self) -> (RxRing, TxRing) {
         (self.rx, self.tx)
     }
+}
+
+impl<U, S> XdpSocket<U, S> {
+    fn into_state<T>(self) -> XdpSocket<U, T> {
+        XdpSocket {
+            sock: self.sock,
+            umem_ref: self.umem_ref,
+            rx: self.rx,
+            tx: self.tx,
+            ifindex: self.ifindex,
+            queue: self.queue,
+            state: PhantomData,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn ifindex(&self) -> u32 {
+        self.ifindex
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn queue(&self) -> u32 {
+        self.queue
+    }
 
     #[inline]
     #[must_use]
@@ -130,6 +221,7 @@ impl<U> XdpSocket<U> {
 #[derive(Debug, Default)]
 pub struct XdpSocketBuilder<U> {
     umem_ref: Option<U>,
+    mode: BindMode,
     rx_size: Option<usize>,
     tx_size: Option<usize>,
     ifindex: Option<u32>,
@@ -141,6 +233,7 @@ impl<U> XdpSocketBuilder<U> {
     pub fn new() -> Self {
         XdpSocketBuilder {
             umem_ref: None,
+            mode: BindMode::Auto,
             rx_size: None,
             tx_size: None,
             ifindex: None,
@@ -176,15 +269,27 @@ impl<U> XdpSocketBuilder<U> {
 impl XdpSocketBuilder<OwnedUmem> {
     #[must_use]
     pub fn owned_umem(mut self, umem: Umem) -> Self {
-        self.umem_ref = Some(OwnedUmem { umem });
+        self.umem_ref = Some(OwnedUmem {
+            umem,
+            mode: BindMode::Auto,
+        });
+        self
+    }
+
+    /// Only the socket that owns the UMEM chooses the mode. Sockets sharing it
+    /// inherit the mode it was bound with.
+    #[must_use]
+    pub fn mode(mut self, mode: BindMode) -> Self {
+        self.mode = mode;
         self
     }
 
     #[must_use]
     pub fn build(self) -> Result<XdpSocket<OwnedUmem>> {
-        let umem_ref = self
+        let mut umem_ref = self
             .umem_ref
             .ok_or_else(|| Error::InvalidArgument("umem must be specified"))?;
+        umem_ref.mode = self.mode;
         let rx_size = self
             .rx_size
             .ok_or_else(|| Error::InvalidArgument("rx_size must be specified"))?;
@@ -203,7 +308,7 @@ impl XdpSocketBuilder<OwnedUmem> {
 
 impl XdpSocketBuilder<SharedUmem> {
     #[must_use]
-    pub fn shared_umem(mut self, xsk: &XdpSocket<OwnedUmem>) -> Self {
+    pub fn shared_umem<S>(mut self, xsk: &XdpSocket<OwnedUmem, S>) -> Self {
         self.umem_ref = Some(SharedUmem { sock: xsk.socket() });
         self
     }
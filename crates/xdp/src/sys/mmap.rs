@@ -24,7 +24,10 @@ pub fn mmap(
     let ret = unsafe { libc::mmap(ptr as *mut _, len, prot, flags, fd, offset) };
 
     if ret == MAP_FAILED {
-        return Err(Error::Mmap(errno()));
+        return Err(Error::Mmap {
+            errno: errno(),
+            len,
+        });
     }
 
     Ok(Mmap {
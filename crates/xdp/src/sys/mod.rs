@@ -4,10 +4,18 @@
 
 use std::{ffi::CString, ptr::NonNull};
 
-use crate::{error::Error, Result};
+use crate::{
+    error::{Errno, Error},
+    Result,
+};
 pub mod mmap;
+pub mod netdev;
+pub mod netlink;
+pub mod preflight;
 pub mod socket;
 
+pub use bpf::error::strerror;
+
 #[must_use]
 pub(crate) fn ptr<T>(val: T) -> *const libc::c_void {
     std::ptr::addr_of!(val) as *const _
@@ -19,8 +27,8 @@ pub(crate) fn mut_ptr<T>(val: T) -> *mut libc::c_void {
 }
 
 #[must_use]
-pub(crate) fn errno() -> i32 {
-    unsafe { *libc::__errno_location() }
+pub(crate) fn errno() -> Errno {
+    Errno::last()
 }
 
 #[must_use]
@@ -28,13 +36,6 @@ pub(crate) fn is_page_aligned<T>(mem: *const T) -> bool {
     mem as u64 & (libc::_SC_PAGE_SIZE as u64 - 1) == 0
 }
 
-#[must_use]
-pub fn strerror(code: i32) -> String {
-    let msg_ptr = unsafe { libc::strerror(code) };
-    let msg_cstr = unsafe { std::ffi::CStr::from_ptr(msg_ptr) };
-    msg_cstr.to_string_lossy().to_string()
-}
-
 #[must_use]
 pub fn ptr_offset<T, S>(addr: NonNull<T>, offset: usize) -> *mut S {
     (usize::from(addr.addr()) + offset) as *mut S
@@ -43,17 +44,34 @@ pub fn ptr_offset<T, S>(addr: NonNull<T>, offset: usize) -> *mut S {
 #[must_use]
 pub fn if_nametoindex(name: String) -> Result<u32> {
     let ret = unsafe {
-        let cstr = CString::new(name).map_err(|_| Error::Efault("bad ifindex name"))?;
+        let cstr = CString::new(name.as_str()).map_err(|_| Error::Efault("bad ifindex name"))?;
         libc::if_nametoindex(cstr.as_ptr())
     };
 
     if ret == 0 {
-        Err(Error::IfNameToIndex(errno()))
+        Err(Error::IfNameToIndex {
+            errno: errno(),
+            ifname: name,
+        })
     } else {
         Ok(ret)
     }
 }
 
+pub fn if_indextoname(ifindex: u32) -> Result<String> {
+    let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let ret = unsafe { libc::if_indextoname(ifindex, buf.as_mut_ptr()) };
+    if ret.is_null() {
+        Err(Error::IfIndexToName {
+            errno: errno(),
+            ifindex,
+        })
+    } else {
+        let name = unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+}
+
 pub fn poll(fd: u32, events: i16) -> i32 {
     let mut pollfd = libc::pollfd {
         fd: fd as i32,
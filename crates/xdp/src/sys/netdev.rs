@@ -0,0 +1,185 @@
+//! Queries and configures network device queues through the ethtool ioctl.
+//!
+//! ```ignore
+//! let channels = netdev::channels("eth0")?;
+//! println!("{} rx queues", channels.rx_queues());
+//! netdev::set_channels("eth0", &Channels { combined: 4, ..channels })?;
+//! ```
+use std::path::Path;
+
+use crate::error::Error;
+use crate::sys::errno;
+use crate::sys::socket::Socket;
+use crate::Result;
+
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+const ETHTOOL_GCHANNELS: u32 = 0x3c;
+const ETHTOOL_SCHANNELS: u32 = 0x3d;
+
+/// Mirror of `struct ethtool_channels` from `linux/ethtool.h`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct EthtoolChannels {
+    cmd: u32,
+    max_rx: u32,
+    max_tx: u32,
+    max_other: u32,
+    max_combined: u32,
+    rx_count: u32,
+    tx_count: u32,
+    other_count: u32,
+    combined_count: u32,
+}
+
+/// Mirror of `struct ifreq`, with the union narrowed to the data pointer.
+#[repr(C)]
+struct IfReq {
+    ifr_name: [libc::c_char; libc::IFNAMSIZ],
+    ifr_data: *mut libc::c_void,
+    _pad: [u8; 16],
+}
+
+/// Queue (channel) counts of a network device. A combined channel has both an
+/// rx and a tx queue, so the number of rx queues is `rx + combined`.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Channels {
+    pub max_rx: u32,
+    pub max_tx: u32,
+    pub max_other: u32,
+    pub max_combined: u32,
+    pub rx: u32,
+    pub tx: u32,
+    pub other: u32,
+    pub combined: u32,
+}
+
+impl Channels {
+    #[inline]
+    #[must_use]
+    pub fn rx_queues(&self) -> u32 {
+        self.rx + self.combined
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn tx_queues(&self) -> u32 {
+        self.tx + self.combined
+    }
+}
+
+impl From<EthtoolChannels> for Channels {
+    fn from(raw: EthtoolChannels) -> Self {
+        Channels {
+            max_rx: raw.max_rx,
+            max_tx: raw.max_tx,
+            max_other: raw.max_other,
+            max_combined: raw.max_combined,
+            rx: raw.rx_count,
+            tx: raw.tx_count,
+            other: raw.other_count,
+            combined: raw.combined_count,
+        }
+    }
+}
+
+/// Query the current and maximum channel counts with `ETHTOOL_GCHANNELS`.
+pub fn channels(ifname: &str) -> Result<Channels> {
+    let mut raw = EthtoolChannels {
+        cmd: ETHTOOL_GCHANNELS,
+        ..Default::default()
+    };
+    ethtool(ifname, "ETHTOOL_GCHANNELS", &mut raw)?;
+    Ok(raw.into())
+}
+
+/// Change the channel counts with `ETHTOOL_SCHANNELS`. The `max_*` fields are
+/// ignored by the kernel. Requires `CAP_NET_ADMIN`.
+pub fn set_channels(ifname: &str, channels: &Channels) -> Result<()> {
+    let mut raw = EthtoolChannels {
+        cmd: ETHTOOL_SCHANNELS,
+        rx_count: channels.rx,
+        tx_count: channels.tx,
+        other_count: channels.other,
+        combined_count: channels.combined,
+        ..Default::default()
+    };
+    ethtool(ifname, "ETHTOOL_SCHANNELS", &mut raw)
+}
+
+/// Number of rx queues on the interface. Not every driver implements the
+/// ethtool channels API, so this falls back to counting the queues in sysfs.
+pub fn rx_queue_count(ifname: &str) -> Result<u32> {
+    match channels(ifname) {
+        Ok(channels) if channels.rx_queues() > 0 => Ok(channels.rx_queues()),
+        Ok(_) => sysfs_rx_queue_count(ifname),
+        Err(err) => sysfs_rx_queue_count(ifname).map_err(|_| err),
+    }
+}
+
+fn sysfs_rx_queue_count(ifname: &str) -> Result<u32> {
+    let dir = Path::new("/sys/class/net").join(ifname).join("queues");
+    let count = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("rx-"))
+        .count();
+    Ok(count as u32)
+}
+
+fn ethtool<T>(ifname: &str, cmd: &'static str, data: &mut T) -> Result<()> {
+    if ifname.len() >= libc::IFNAMSIZ {
+        return Err(Error::InvalidArgument("interface name is too long"));
+    }
+
+    let mut ifr = IfReq {
+        ifr_name: [0; libc::IFNAMSIZ],
+        ifr_data: data as *mut T as *mut _,
+        _pad: [0; 16],
+    };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(ifname.bytes()) {
+        *dst = src as libc::c_char;
+    }
+
+    let ethtool_error = |errno| Error::Ethtool {
+        errno,
+        ifname: ifname.to_owned(),
+        cmd,
+    };
+    // Not Socket::create, whose error would carry the AF_XDP socket hint.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(ethtool_error(errno()));
+    }
+    let sock = Socket { fd };
+    let ret = unsafe { libc::ioctl(sock.fd, SIOCETHTOOL, &mut ifr) };
+    let err = errno();
+    sock.close();
+
+    if ret < 0 {
+        return Err(ethtool_error(err));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_struct_layouts() {
+        assert_eq!(36, std::mem::size_of::<EthtoolChannels>());
+        assert_eq!(40, std::mem::size_of::<IfReq>());
+    }
+
+    #[test]
+    fn test_rx_queues_include_combined_channels() {
+        let channels = Channels {
+            rx: 1,
+            combined: 4,
+            ..Default::default()
+        };
+
+        assert_eq!(5, channels.rx_queues());
+        assert_eq!(4, channels.tx_queues());
+    }
+}
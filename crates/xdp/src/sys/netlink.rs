@@ -0,0 +1,462 @@
+//! Minimal netlink client for querying what a network device supports.
+//!
+//! The XDP feature set and zero-copy limits come from the `netdev` generic
+//! netlink family (Linux 6.3+), while the attached XDP programs come from the
+//! `IFLA_XDP` attribute of an rtnetlink link message.
+//!
+//! ```ignore
+//! let features = netlink::device_features(ifindex)?;
+//! if features.xdp.zero_copy() { ... }
+//! let attached = netlink::xdp_attachment(ifindex)?;
+//! println!("{:?} program {:?}", attached.mode, attached.prog_id);
+//! ```
+use crate::error::Error;
+use crate::sys::errno;
+use crate::sys::socket::Socket;
+use crate::Result;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLA_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLMSG_ERROR: u16 = 0x2;
+const NLMSG_DONE: u16 = 0x3;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+const NETDEV_FAMILY_NAME: &str = "netdev";
+const NETDEV_CMD_DEV_GET: u8 = 1;
+const NETDEV_A_DEV_IFINDEX: u16 = 1;
+const NETDEV_A_DEV_XDP_FEATURES: u16 = 3;
+const NETDEV_A_DEV_XDP_ZC_MAX_SEGS: u16 = 4;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_GETLINK: u16 = 18;
+const IFLA_XDP: u16 = 43;
+const IFLA_XDP_ATTACHED: u16 = 2;
+const IFLA_XDP_PROG_ID: u16 = 4;
+const IFLA_XDP_DRV_PROG_ID: u16 = 5;
+const IFLA_XDP_SKB_PROG_ID: u16 = 6;
+const IFLA_XDP_HW_PROG_ID: u16 = 7;
+
+/// Bitset of `enum netdev_xdp_act` values advertised by a driver.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct XdpFeatures(pub u64);
+
+impl XdpFeatures {
+    pub const BASIC: u64 = 1 << 0;
+    pub const REDIRECT: u64 = 1 << 1;
+    pub const NDO_XMIT: u64 = 1 << 2;
+    pub const XSK_ZEROCOPY: u64 = 1 << 3;
+    pub const HW_OFFLOAD: u64 = 1 << 4;
+    pub const RX_SG: u64 = 1 << 5;
+    pub const NDO_XMIT_SG: u64 = 1 << 6;
+
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, flags: u64) -> bool {
+        self.0 & flags == flags
+    }
+
+    /// The driver runs XDP programs natively (`XDP_FLAGS_DRV_MODE`).
+    #[inline]
+    #[must_use]
+    pub fn native(&self) -> bool {
+        self.contains(Self::BASIC)
+    }
+
+    /// The driver supports `XDP_REDIRECT`, which is needed to reach a socket.
+    #[inline]
+    #[must_use]
+    pub fn redirect(&self) -> bool {
+        self.contains(Self::REDIRECT)
+    }
+
+    /// The device can be the target of a redirect from another interface.
+    #[inline]
+    #[must_use]
+    pub fn redirect_target(&self) -> bool {
+        self.contains(Self::NDO_XMIT)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn zero_copy(&self) -> bool {
+        self.contains(Self::XSK_ZEROCOPY)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn hw_offload(&self) -> bool {
+        self.contains(Self::HW_OFFLOAD)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct DeviceFeatures {
+    pub xdp: XdpFeatures,
+    /// Maximum number of buffers in a multi-buffer zero-copy frame. Only
+    /// reported by Linux 6.6+ for devices that support zero-copy.
+    pub zc_max_segs: Option<u32>,
+}
+
+/// Where the XDP program on an interface is attached, from `XDP_ATTACHED_*`.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum AttachMode {
+    #[default]
+    None,
+    Driver,
+    Generic,
+    Hardware,
+    Multi,
+}
+
+impl From<u8> for AttachMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => AttachMode::Driver,
+            2 => AttachMode::Generic,
+            3 => AttachMode::Hardware,
+            4 => AttachMode::Multi,
+            _ => AttachMode::None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct XdpAttachment {
+    pub mode: AttachMode,
+    /// Id of the attached program, unless programs are attached in several
+    /// modes at once, in which case the per-mode ids are set instead.
+    pub prog_id: Option<u32>,
+    pub drv_prog_id: Option<u32>,
+    pub skb_prog_id: Option<u32>,
+    pub hw_prog_id: Option<u32>,
+}
+
+/// Query the XDP features of a device through the `netdev` generic netlink
+/// family.
+pub fn device_features(ifindex: u32) -> Result<DeviceFeatures> {
+    let family = resolve_family(NETDEV_FAMILY_NAME)?;
+
+    let mut req = Request::new(family, NLM_F_REQUEST | NLM_F_ACK);
+    req.push(&genl_header(NETDEV_CMD_DEV_GET, 1));
+    req.push_attr(NETDEV_A_DEV_IFINDEX, &ifindex.to_ne_bytes());
+
+    let replies = transact(libc::NETLINK_GENERIC, req, "NETDEV_CMD_DEV_GET")?;
+    let payload = replies
+        .iter()
+        .find(|(typ, _)| *typ == family)
+        .map(|(_, payload)| payload)
+        .ok_or(Error::NotFound("no netdev reply for interface"))?;
+
+    let mut features = DeviceFeatures::default();
+    for (typ, value) in Attrs::new(payload.get(4..).unwrap_or_default()) {
+        match typ {
+            NETDEV_A_DEV_XDP_FEATURES => features.xdp = XdpFeatures(read_u64(value)?),
+            NETDEV_A_DEV_XDP_ZC_MAX_SEGS => features.zc_max_segs = Some(read_u32(value)?),
+            _ => {}
+        }
+    }
+    Ok(features)
+}
+
+/// Query which XDP programs are attached to an interface through rtnetlink.
+pub fn xdp_attachment(ifindex: u32) -> Result<XdpAttachment> {
+    let mut req = Request::new(RTM_GETLINK, NLM_F_REQUEST | NLM_F_ACK);
+    req.push(&ifinfo_header(ifindex));
+
+    let replies = transact(libc::NETLINK_ROUTE, req, "RTM_GETLINK")?;
+    let payload = replies
+        .iter()
+        .find(|(typ, _)| *typ == RTM_NEWLINK)
+        .map(|(_, payload)| payload)
+        .ok_or(Error::NotFound("no link reply for interface"))?;
+
+    let mut attachment = XdpAttachment::default();
+    let xdp = Attrs::new(payload.get(16..).unwrap_or_default())
+        .find(|(typ, _)| *typ == IFLA_XDP)
+        .map(|(_, value)| value)
+        .unwrap_or_default();
+
+    for (typ, value) in Attrs::new(xdp) {
+        match typ {
+            IFLA_XDP_ATTACHED => {
+                attachment.mode = value.first().copied().unwrap_or_default().into();
+            }
+            IFLA_XDP_PROG_ID => attachment.prog_id = Some(read_u32(value)?),
+            IFLA_XDP_DRV_PROG_ID => attachment.drv_prog_id = Some(read_u32(value)?),
+            IFLA_XDP_SKB_PROG_ID => attachment.skb_prog_id = Some(read_u32(value)?),
+            IFLA_XDP_HW_PROG_ID => attachment.hw_prog_id = Some(read_u32(value)?),
+            _ => {}
+        }
+    }
+    Ok(attachment)
+}
+
+/// Look up the id of a generic netlink family by name.
+fn resolve_family(name: &str) -> Result<u16> {
+    let mut name = name.as_bytes().to_vec();
+    name.push(0);
+
+    let mut req = Request::new(GENL_ID_CTRL, NLM_F_REQUEST | NLM_F_ACK);
+    req.push(&genl_header(CTRL_CMD_GETFAMILY, 1));
+    req.push_attr(CTRL_ATTR_FAMILY_NAME, &name);
+
+    let replies = transact(libc::NETLINK_GENERIC, req, "CTRL_CMD_GETFAMILY")?;
+    replies
+        .iter()
+        .filter(|(typ, _)| *typ == GENL_ID_CTRL)
+        .flat_map(|(_, payload)| Attrs::new(payload.get(4..).unwrap_or_default()))
+        .find(|(typ, _)| *typ == CTRL_ATTR_FAMILY_ID)
+        .map(|(_, value)| read_u16(value))
+        .unwrap_or(Err(Error::NotFound("generic netlink family not found")))
+}
+
+/// Send a single request and collect every reply up to the final ack, as
+/// pairs of message type and payload.
+fn transact(protocol: i32, req: Request, op: &'static str) -> Result<Vec<(u16, Vec<u8>)>> {
+    // Not Socket::create, whose error would carry the AF_XDP socket hint.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            protocol,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::Netlink { errno: errno(), op });
+    }
+    let sock = Socket { fd };
+    let ret = exchange(&sock, &req.finish(), op);
+    sock.close();
+    ret
+}
+
+fn exchange(sock: &Socket, msg: &[u8], op: &'static str) -> Result<Vec<(u16, Vec<u8>)>> {
+    if unsafe { libc::send(sock.fd, msg.as_ptr() as *const _, msg.len(), 0) } < 0 {
+        return Err(Error::Netlink { errno: errno(), op });
+    }
+
+    let mut replies = vec![];
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let len = unsafe { libc::recv(sock.fd, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+        if len < 0 {
+            return Err(Error::Netlink { errno: errno(), op });
+        }
+
+        for (typ, payload) in Messages::new(&buf[..len as usize]) {
+            match typ {
+                NLMSG_DONE => return Ok(replies),
+                NLMSG_ERROR => {
+                    return match read_i32(payload.get(..4).unwrap_or_default())? {
+                        0 => Ok(replies),
+                        code => Err(Error::Netlink {
+                            errno: code.abs().into(),
+                            op,
+                        }),
+                    }
+                }
+                typ => replies.push((typ, payload.to_vec())),
+            }
+        }
+    }
+}
+
+#[must_use]
+fn genl_header(cmd: u8, version: u8) -> [u8; 4] {
+    [cmd, version, 0, 0]
+}
+
+/// `struct ifinfomsg` selecting a single interface.
+#[must_use]
+fn ifinfo_header(ifindex: u32) -> [u8; 16] {
+    let mut hdr = [0u8; 16];
+    hdr[0] = libc::AF_UNSPEC as u8;
+    hdr[4..8].copy_from_slice(&ifindex.to_ne_bytes());
+    hdr
+}
+
+/// A netlink message under construction. The header length is filled in by
+/// [Request::finish].
+struct Request {
+    buf: Vec<u8>,
+}
+
+impl Request {
+    fn new(typ: u16, flags: u16) -> Self {
+        let mut buf = vec![0u8; NLMSG_HDRLEN];
+        buf[4..6].copy_from_slice(&typ.to_ne_bytes());
+        buf[6..8].copy_from_slice(&flags.to_ne_bytes());
+        buf[8..12].copy_from_slice(&1u32.to_ne_bytes());
+        Request { buf }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        self.buf.resize(align(self.buf.len()), 0);
+    }
+
+    fn push_attr(&mut self, typ: u16, value: &[u8]) {
+        let len = (NLA_HDRLEN + value.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&typ.to_ne_bytes());
+        self.push(value);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// Iterates over the netlink messages in a datagram, yielding the message
+/// type and the payload after the header.
+struct Messages<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Messages<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Messages { buf }
+    }
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < NLMSG_HDRLEN {
+            return None;
+        }
+        let len = u32::from_ne_bytes(self.buf[0..4].try_into().ok()?) as usize;
+        let typ = u16::from_ne_bytes(self.buf[4..6].try_into().ok()?);
+        if len < NLMSG_HDRLEN || len > self.buf.len() {
+            return None;
+        }
+        let payload = &self.buf[NLMSG_HDRLEN..len];
+        self.buf = &self.buf[align(len).min(self.buf.len())..];
+        Some((typ, payload))
+    }
+}
+
+/// Iterates over a sequence of netlink attributes, yielding the attribute
+/// type (without the nested and byte order flags) and its value.
+pub(crate) struct Attrs<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Attrs<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Attrs { buf }
+    }
+}
+
+impl<'a> Iterator for Attrs<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < NLA_HDRLEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([self.buf[0], self.buf[1]]) as usize;
+        let typ = u16::from_ne_bytes([self.buf[2], self.buf[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HDRLEN || len > self.buf.len() {
+            return None;
+        }
+        let value = &self.buf[NLA_HDRLEN..len];
+        self.buf = &self.buf[align(len).min(self.buf.len())..];
+        Some((typ, value))
+    }
+}
+
+#[inline]
+#[must_use]
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn read_u16(value: &[u8]) -> Result<u16> {
+    Ok(u16::from_ne_bytes(value.try_into().map_err(|_| {
+        Error::Efault("netlink attribute has the wrong size")
+    })?))
+}
+
+fn read_u32(value: &[u8]) -> Result<u32> {
+    Ok(u32::from_ne_bytes(value.try_into().map_err(|_| {
+        Error::Efault("netlink attribute has the wrong size")
+    })?))
+}
+
+fn read_i32(value: &[u8]) -> Result<i32> {
+    Ok(i32::from_ne_bytes(value.try_into().map_err(|_| {
+        Error::Efault("netlink attribute has the wrong size")
+    })?))
+}
+
+fn read_u64(value: &[u8]) -> Result<u64> {
+    Ok(u64::from_ne_bytes(value.try_into().map_err(|_| {
+        Error::Efault("netlink attribute has the wrong size")
+    })?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_is_padded_and_sized() {
+        let mut req = Request::new(GENL_ID_CTRL, NLM_F_REQUEST);
+        req.push(&genl_header(CTRL_CMD_GETFAMILY, 1));
+        req.push_attr(CTRL_ATTR_FAMILY_NAME, b"netdev\0");
+
+        let msg = req.finish();
+
+        // 16 byte header, 4 byte genl header, 4 byte attr header, 7 bytes
+        // of name padded to 8.
+        assert_eq!(32, msg.len());
+        assert_eq!(32, u32::from_ne_bytes(msg[0..4].try_into().unwrap()));
+        assert_eq!(11, u16::from_ne_bytes(msg[20..22].try_into().unwrap()));
+    }
+
+    #[test]
+    fn test_attrs_roundtrip_through_request() {
+        let mut req = Request::new(0, 0);
+        req.push_attr(IFLA_XDP_ATTACHED, &[1]);
+        req.push_attr(IFLA_XDP_PROG_ID, &42u32.to_ne_bytes());
+
+        let msg = req.finish();
+        let attrs = Attrs::new(&msg[NLMSG_HDRLEN..]).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (IFLA_XDP_ATTACHED, &[1u8][..]),
+                (IFLA_XDP_PROG_ID, &42u32.to_ne_bytes()[..])
+            ],
+            attrs
+        );
+    }
+
+    #[test]
+    fn test_attrs_stops_on_truncated_attribute() {
+        let buf = [8u8, 0, 1, 0, 0, 0];
+
+        assert_eq!(0, Attrs::new(&buf).count());
+    }
+
+    #[test]
+    fn test_xdp_features() {
+        let features = XdpFeatures(XdpFeatures::BASIC | XdpFeatures::REDIRECT);
+
+        assert!(features.native());
+        assert!(features.redirect());
+        assert!(!features.zero_copy());
+    }
+}
@@ -0,0 +1,315 @@
+//! Checks that the process has the privileges and resources needed to create
+//! `AF_XDP` sockets before any of them are created. Registering a UMEM or
+//! creating a BPF map fails with an opaque errno when something is missing, so
+//! every check runs and all the problems are reported together.
+//!
+//! ```ignore
+//! Preflight::new()
+//!     .ifname("eth0")
+//!     .queues([0, 1])
+//!     .memlock(16 * 1024 * 1024)
+//!     .raise_memlock(true)
+//!     .run()?;
+//! ```
+use std::fmt;
+
+use crate::error::Error;
+use crate::sys::if_nametoindex;
+use crate::sys::netdev::rx_queue_count;
+use crate::Result;
+
+/// Capabilities that `AF_XDP` and BPF operations depend on.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Capability {
+    NetAdmin,
+    NetRaw,
+    IpcLock,
+    SysAdmin,
+    Bpf,
+}
+
+impl Capability {
+    #[must_use]
+    fn bit(self) -> u32 {
+        match self {
+            Capability::NetAdmin => 12,
+            Capability::NetRaw => 13,
+            Capability::IpcLock => 14,
+            Capability::SysAdmin => 21,
+            Capability::Bpf => 39,
+        }
+    }
+
+    /// Whether the capability is present in an effective capability set.
+    /// Kernels older than 5.8 have no `CAP_BPF`, and `CAP_SYS_ADMIN` grants
+    /// everything it does.
+    #[must_use]
+    pub fn is_granted(self, effective: u64) -> bool {
+        let has = |cap: Capability| effective & (1 << cap.bit()) != 0;
+        match self {
+            Capability::Bpf => has(Capability::Bpf) || has(Capability::SysAdmin),
+            cap => has(cap),
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::NetAdmin => write!(f, "CAP_NET_ADMIN"),
+            Capability::NetRaw => write!(f, "CAP_NET_RAW"),
+            Capability::IpcLock => write!(f, "CAP_IPC_LOCK"),
+            Capability::SysAdmin => write!(f, "CAP_SYS_ADMIN"),
+            Capability::Bpf => write!(f, "CAP_BPF"),
+        }
+    }
+}
+
+/// A single failed preflight check.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    MissingCapability(Capability),
+    MemlockTooLow { limit: u64, required: u64 },
+    InterfaceNotFound(String),
+    QueueOutOfRange { queue: u32, count: u32 },
+    Unreadable(&'static str),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingCapability(cap) => write!(f, "missing capability {cap}"),
+            Problem::MemlockTooLow { limit, required } => write!(
+                f,
+                "RLIMIT_MEMLOCK is {limit} bytes but {required} are required \
+                 (raise it with `ulimit -l` or enable raise_memlock)"
+            ),
+            Problem::InterfaceNotFound(ifname) => write!(f, "interface \"{ifname}\" not found"),
+            Problem::QueueOutOfRange { queue, count } => write!(
+                f,
+                "queue {queue} does not exist, the interface has {count} rx queues"
+            ),
+            Problem::Unreadable(what) => write!(f, "could not read {what}"),
+        }
+    }
+}
+
+/// Every problem found by a preflight run.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostics(pub Vec<Problem>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for problem in &self.0 {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Preflight {
+    ifname: Option<String>,
+    queues: Vec<u32>,
+    memlock: u64,
+    raise_memlock: bool,
+    capabilities: Vec<Capability>,
+}
+
+impl Preflight {
+    /// Checks for `CAP_NET_RAW`, which every `AF_XDP` socket needs.
+    #[must_use]
+    pub fn new() -> Self {
+        Preflight {
+            capabilities: vec![Capability::NetRaw],
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn ifname(mut self, ifname: &str) -> Self {
+        self.ifname = Some(ifname.to_owned());
+        self
+    }
+
+    #[must_use]
+    pub fn queues<I>(mut self, queues: I) -> Self
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        self.queues = queues.into_iter().collect();
+        self
+    }
+
+    /// Number of bytes that will be locked into memory, usually the size of
+    /// the UMEM frame buffer.
+    #[must_use]
+    pub fn memlock(mut self, bytes: u64) -> Self {
+        self.memlock = bytes;
+        self
+    }
+
+    /// Try to raise `RLIMIT_MEMLOCK` when it is lower than required.
+    #[must_use]
+    pub fn raise_memlock(mut self, raise_memlock: bool) -> Self {
+        self.raise_memlock = raise_memlock;
+        self
+    }
+
+    #[must_use]
+    pub fn capability(mut self, cap: Capability) -> Self {
+        if !self.capabilities.contains(&cap) {
+            self.capabilities.push(cap);
+        }
+        self
+    }
+
+    /// Run every check, returning [Error::Preflight] listing all the problems
+    /// found.
+    pub fn run(self) -> Result<()> {
+        let mut problems = vec![];
+
+        let effective = effective_capabilities();
+        match effective {
+            Some(effective) => problems.extend(
+                self.capabilities
+                    .iter()
+                    .filter(|cap| !cap.is_granted(effective))
+                    .map(|cap| Problem::MissingCapability(*cap)),
+            ),
+            None => problems.push(Problem::Unreadable("process capabilities")),
+        }
+
+        let ipc_lock = effective.is_some_and(|caps| Capability::IpcLock.is_granted(caps));
+        if self.memlock > 0 && !ipc_lock {
+            if self.raise_memlock {
+                raise_memlock(self.memlock);
+            }
+            match memlock_limit() {
+                Some(limit) if limit < self.memlock => problems.push(Problem::MemlockTooLow {
+                    limit,
+                    required: self.memlock,
+                }),
+                Some(_) => {}
+                None => problems.push(Problem::Unreadable("RLIMIT_MEMLOCK")),
+            }
+        }
+
+        if let Some(ifname) = self.ifname {
+            if if_nametoindex(ifname.clone()).is_err() {
+                problems.push(Problem::InterfaceNotFound(ifname));
+            } else if let Ok(count) = rx_queue_count(&ifname) {
+                problems.extend(
+                    self.queues
+                        .iter()
+                        .filter(|queue| **queue >= count)
+                        .map(|queue| Problem::QueueOutOfRange {
+                            queue: *queue,
+                            count,
+                        }),
+                );
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Preflight(Diagnostics(problems)))
+        }
+    }
+}
+
+/// Current soft `RLIMIT_MEMLOCK` in bytes, with `u64::MAX` meaning unlimited.
+#[must_use]
+pub fn memlock_limit() -> Option<u64> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    match unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim) } {
+        0 => Some(rlim.rlim_cur),
+        _ => None,
+    }
+}
+
+/// Raise the soft `RLIMIT_MEMLOCK` to at least `required` bytes. Lifting the
+/// hard limit needs `CAP_SYS_RESOURCE`, so without it the soft limit is only
+/// raised as far as the hard limit allows. Returns the resulting soft limit.
+pub fn raise_memlock(required: u64) -> Option<u64> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim) } != 0 {
+        return None;
+    }
+    if rlim.rlim_cur >= required {
+        return Some(rlim.rlim_cur);
+    }
+
+    let unlimited = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &unlimited) } == 0 {
+        return Some(libc::RLIM_INFINITY);
+    }
+
+    let capped = libc::rlimit {
+        rlim_cur: rlim.rlim_max,
+        rlim_max: rlim.rlim_max,
+    };
+    match unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &capped) } {
+        0 => Some(rlim.rlim_max),
+        _ => Some(rlim.rlim_cur),
+    }
+}
+
+/// Effective capability set of the current process, read from procfs.
+#[must_use]
+pub fn effective_capabilities() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_cap_eff(&status)
+}
+
+#[must_use]
+fn parse_cap_eff(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cap_eff() {
+        let status = "Name:\tcat\nCapPrm:\t0000000000000000\nCapEff:\t0000000000002000\n";
+
+        assert_eq!(Some(0x2000), parse_cap_eff(status));
+    }
+
+    #[test]
+    fn test_sys_admin_grants_bpf() {
+        let sys_admin = 1 << 21;
+
+        assert!(Capability::Bpf.is_granted(sys_admin));
+        assert!(!Capability::NetRaw.is_granted(sys_admin));
+    }
+
+    #[test]
+    fn test_diagnostics_lists_every_problem() {
+        let diagnostics = Diagnostics(vec![
+            Problem::MissingCapability(Capability::NetRaw),
+            Problem::QueueOutOfRange { queue: 4, count: 2 },
+        ]);
+
+        assert_eq!(
+            "\n  - missing capability CAP_NET_RAW\
+             \n  - queue 4 does not exist, the interface has 2 rx queues",
+            diagnostics.to_string()
+        );
+    }
+}
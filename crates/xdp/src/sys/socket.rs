@@ -6,7 +6,7 @@
 //! ```
 use std::mem::size_of;
 
-use crate::error::Error;
+use crate::error::{Errno, Error};
 use crate::sys::errno;
 use crate::Result;
 
@@ -28,7 +28,7 @@ impl Socket {
         let fd = unsafe { libc::socket(domain, typ, protocol) };
 
         if fd == -1 {
-            return Err(Error::Socket(errno()));
+            return Err(Error::Socket { errno: errno() });
         }
 
         Ok(Socket { fd })
@@ -44,7 +44,10 @@ impl Socket {
                 opt_value as _,
                 size_of::<T>() as u32,
             ) {
-                ret if ret < 0 => Err(Error::SetSockOpt(super::errno())),
+                ret if ret < 0 => Err(Error::SetSockOpt {
+                    errno: super::errno(),
+                    opt: opt_name,
+                }),
                 _ => Ok(()),
             }
         }
@@ -56,8 +59,15 @@ impl Socket {
         O::try_get(self)
     }
 
+    /// Close the file descriptor. The socket must not be used afterwards.
+    pub fn close(self) {
+        unsafe { libc::close(self.fd) };
+    }
+
+    /// Returns the bare errno on failure, because only the caller knows which
+    /// interface and queue the address refers to.
     #[must_use]
-    pub fn bind<T>(&self, sockaddr: &T) -> Result<()> {
+    pub fn bind<T>(&self, sockaddr: &T) -> std::result::Result<(), Errno> {
         let ret = unsafe {
             libc::bind(
                 self.fd,
@@ -67,7 +77,7 @@ impl Socket {
         };
 
         if ret == -1 {
-            return Err(Error::Bind(errno()));
+            return Err(errno());
         }
 
         return Ok(());
@@ -117,8 +127,27 @@ unsafe fn getsockopt(
     optlen: *mut libc::socklen_t,
 ) -> Result<()> {
     if unsafe { libc::getsockopt(sockfd, level, optname, optval, optlen) } < 0 {
-        Err(Error::GetSockOpt(errno()))
+        Err(Error::GetSockOpt {
+            errno: errno(),
+            opt: optname as u32,
+        })
     } else {
         Ok(())
     }
 }
+
+/// Name of a `SOL_XDP` socket option, used to give errors some context.
+#[must_use]
+pub fn sockopt_name(opt: u32) -> &'static str {
+    match opt {
+        xdp_sys::XDP_MMAP_OFFSETS => "XDP_MMAP_OFFSETS",
+        xdp_sys::XDP_RX_RING => "XDP_RX_RING",
+        xdp_sys::XDP_TX_RING => "XDP_TX_RING",
+        xdp_sys::XDP_UMEM_REG => "XDP_UMEM_REG",
+        xdp_sys::XDP_UMEM_FILL_RING => "XDP_UMEM_FILL_RING",
+        xdp_sys::XDP_UMEM_COMPLETION_RING => "XDP_UMEM_COMPLETION_RING",
+        xdp_sys::XDP_STATISTICS => "XDP_STATISTICS",
+        xdp_sys::XDP_OPTIONS => "XDP_OPTIONS",
+        _ => "unknown option",
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::Error;
-use crate::ring::{CompRing, FillRing, RingBuffer};
+use crate::ring::{CompRing, FillRing, RingBuffer, RingKind};
 use crate::sys::mmap::{Behavior, Protection, Visibility};
 use crate::sys::ptr_offset;
 use crate::sys::socket::{Socket, XdpMmapOffsets};
@@ -55,8 +55,10 @@ impl Umem {
         )?;
 
         let offsets = sock.get_opt::<XdpMmapOffsets>()?;
-        let fill = register_fill_ring(&sock, frame_count as usize, &offsets.fr)?;
-        let comp = register_completion_ring(&sock, frame_count as usize, &offsets.cr)?;
+        let fill = register_fill_ring(&sock, frame_count as usize, &offsets.fr)
+            .map_err(|err| err.in_ring(RingKind::Fill))?;
+        let comp = register_completion_ring(&sock, frame_count as usize, &offsets.cr)
+            .map_err(|err| err.in_ring(RingKind::Completion))?;
 
         Ok(Umem {
             sock,
@@ -74,6 +76,29 @@ impl Umem {
         (self.fill, self.comp)
     }
 
+    /// Hand every frame in the buffer to the kernel by enqueueing its address
+    /// on the fill ring, stopping early if the ring fills up. Returns the number
+    /// of frames that were enqueued.
+    pub fn prefill(&mut self) -> usize {
+        let mut fill = self.fill;
+        (0..self.frame_count as u64)
+            .map(|i| i * self.frame_size as u64)
+            .take_while(|addr| fill.enqueue(*addr))
+            .count()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn frame_size(&self) -> u32 {
+        self.frame_size
+    }
+
     #[must_use]
     pub fn frame(&mut self, addr: u64) -> &[u8] {
         unsafe {
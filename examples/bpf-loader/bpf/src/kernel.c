@@ -0,0 +1,29 @@
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+/**
+ * Map of queue index to AF_XDP sockets
+ */
+struct {
+  __uint(type, BPF_MAP_TYPE_XSKMAP);
+  __type(key, __u32);
+  __type(value, __u32);
+  __uint(max_entries, 64);
+} xsks_map SEC(".maps");
+
+/**
+ * Redirect the packet to the socket bound to the queue it arrived on, and
+ * drop it when there is none.
+ */
+SEC("xdp")
+int xdp_try_pass_to_xsk(struct xdp_md* ctx) {
+  int index = ctx->rx_queue_index;
+
+  if (bpf_map_lookup_elem(&xsks_map, &index)) {
+    return bpf_redirect_map(&xsks_map, index, 0);
+  }
+
+  return XDP_DROP;
+}
+
+char _license[] SEC("license") = "GPL";
@@ -0,0 +1,4 @@
+fn main() -> Result<(), bpf_build::Error> {
+    bpf_build::BpfBuilder::new("bpf/src/kernel.c").compile()?;
+    Ok(())
+}
@@ -1,32 +1,117 @@
+/// The example program, compiled from `bpf/src/kernel.c` by the build script.
+const KERNEL_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/kernel.o"));
+
 /// load a BPF program into the XDP hook on a given interface
 #[derive(argh::FromArgs, Debug)]
 struct Args {
-    /// BPF program object file
-    #[argh(positional)]
-    filepath: String,
     /// name of the program
     #[argh(positional)]
     program: String,
     /// network interface index
     #[argh(positional)]
     ifindex: u32,
+    /// BPF object file, instead of the example object built into the loader
+    #[argh(option)]
+    file: Option<String>,
+    /// XDP mode: auto, generic, native or hardware
+    #[argh(option, default = "String::from(\"auto\")")]
+    mode: String,
 }
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = argh::from_env::<Args>();
 
+    let mode = match args.mode.as_str() {
+        "auto" => bpf::XdpMode::Auto,
+        "generic" => bpf::XdpMode::Generic,
+        "native" => bpf::XdpMode::Native,
+        "hardware" => bpf::XdpMode::Hardware,
+        mode => return Err(format!("unknown XDP mode \"{mode}\"").into()),
+    };
+
+    let source = match &args.file {
+        Some(path) => format!("file \"{path}\""),
+        None => "the example object".to_owned(),
+    };
     println!(
-        "Loading file \"{}\" with program \"{}\" onto ifindex {}",
-        args.filepath, args.program, args.ifindex
+        "Loading {} with program \"{}\" onto ifindex {} in {:?} mode",
+        source, args.program, args.ifindex, mode
     );
 
-    let obj_buf = std::fs::read(args.filepath)?;
-    let obj = bpf::Object::create(&obj_buf)?;
-    obj.load()?;
+    let builder = bpf::ObjectBuilder::new().verifier_log(1 << 20);
+    let mut obj = match &args.file {
+        Some(path) => builder.open_file(path)?,
+        None => builder.open_memory(KERNEL_OBJECT)?,
+    };
+    for prog in obj.programs() {
+        println!(
+            "  program {} ({}, section \"{}\", attach type {})",
+            prog.name(),
+            bpf::error::prog_type_name(prog.prog_type()),
+            prog.section_name().unwrap_or_default(),
+            prog.expected_attach_type()
+                .map(bpf::error::attach_type_name)
+                .unwrap_or_default(),
+        );
+    }
+    for map in obj.maps() {
+        println!(
+            "  map {} ({}, {} entries)",
+            map.name(),
+            bpf::error::map_type_name(map.map_type()),
+            map.max_entries()
+        );
+    }
+
+    // Only the program being attached has to pass the verifier.
+    for mut prog in obj.programs_mut() {
+        let autoload = prog.name() == args.program;
+        prog.set_autoload(autoload)?;
+    }
+
+    // Sockets are looked up by rx queue, so the XSKMAP needs an entry for
+    // every queue on the interface.
+    let queue_count = xdp::sys::netdev::rx_queue_count(&xdp::sys::if_indextoname(args.ifindex)?)?;
+    for mut map in obj.maps_mut() {
+        if map.map_type() == bpf::libbpf_sys::BPF_MAP_TYPE_XSKMAP {
+            map.set_max_entries(queue_count)?;
+        }
+    }
+
+    if let Err(err) = obj.load() {
+        if let Some(log) = err.verifier_log() {
+            eprintln!("{log}");
+        }
+        return Err(err.into());
+    }
 
     let prog = obj.find_program(&args.program)?;
-    prog.attach_xdp(args.ifindex)?;
+    let opts = bpf::XdpAttachOptions::new()
+        .mode(mode)
+        .update_if_noexist(true);
+    let link = prog.attach_xdp_with(args.ifindex, opts)?;
+
+    // Count the program's runs while it is attached, if we're allowed to.
+    let stats = bpf::enable_stats()
+        .inspect_err(|err| eprintln!("Not counting runs: {err}"))
+        .ok();
+
+    println!("{} loaded. Press enter to detach.", args.program);
+    std::io::stdin().read_line(&mut String::new())?;
+
+    if stats.is_some() {
+        let run_stats = prog.run_stats()?;
+        match run_stats.average() {
+            Some(average) => println!(
+                "{} ran {} times, {} ns on average",
+                args.program,
+                run_stats.run_count,
+                average.as_nanos()
+            ),
+            None => println!("{} did not run", args.program),
+        }
+    }
 
-    println!("{} loaded. Ctrl+C to stop.", args.program);
-    loop {}
+    link.detach()?;
+    Ok(())
 }
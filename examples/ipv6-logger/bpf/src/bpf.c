@@ -20,7 +20,8 @@ struct {
  * parallelize packet ingress. But I haven't tested this because laptop NICs
  * only have one rx/tx ring queue.
  *
- * These queues are visible in sysfs (/sys/class/net/<ifname>/queues)
+ * These queues are visible in sysfs (/sys/class/net/<ifname>/queues), and
+ * `xdp::sys::netdev::channels` reports how many the driver has configured.
  */
 SEC("xdp")
 int pass_to_socket(struct xdp_md* ctx) {
@@ -0,0 +1,10 @@
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let object = bpf_build::BpfBuilder::new("bpf/src/bpf.c").compile()?;
+    // Typed access to the object's maps and programs, so a change to bpf.c
+    // that main.rs doesn't follow fails to compile.
+    let skel = bpf::SkeletonGenerator::new("logger").generate(&std::fs::read(&object)?)?;
+    std::fs::write(object.with_file_name("bpf.skel.rs"), skel)?;
+    Ok(())
+}
@@ -1,18 +1,23 @@
 #![feature(ip_bits)]
 
 use std::error::Error;
+use std::time::Duration;
 use xdp::channel::{DeviceConfig, SockConfig, UmemConfig, XdpChannel};
 use xdp::constants::{
     DEFAULT_CONS_NUM_DESCS, DEFAULT_FRAME_HEADROOM, DEFAULT_PROD_NUM_DESCS, FRAME_SIZE, NUM_FRAMES,
 };
+use xdp::profile::Profiler;
 use xdp::sys::if_nametoindex;
 
+/// The BPF program, compiled from `bpf/src/bpf.c` by the build script.
+const BPF_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/bpf.o"));
+
+// The `logger` skeleton of the object, generated by the build script.
+include!(concat!(env!("OUT_DIR"), "/bpf.skel.rs"));
+
 /// count packets arriving on a given network interface
 #[derive(argh::FromArgs, Debug)]
 struct Args {
-    /// BPF program object file
-    #[argh(positional)]
-    filepath: String,
     /// network interface name
     #[argh(positional)]
     ifname: String,
@@ -45,22 +50,25 @@ fn main() -> Result<(), Box<dyn Error>> {
         .umem(umem)
         .sockets(socks)
         .netdev(netdev)
+        .bind()
+        .prefill(true)
+        .own_program(true)
         .build()?;
 
+    let skel = logger::OpenSkeleton::open(BPF_OBJECT)?.load()?;
+    let progs = skel.progs()?;
+    // Held until exit, since dropping the link detaches the program.
+    let _link = progs.pass_to_socket.attach_xdp(ifindex)?;
+    chan.register(&skel.maps()?.xsks_map)?;
+
+    // Reports the time spent in the program next to the userspace throughput.
+    let mut profiler = Profiler::new(&progs.pass_to_socket)?;
+
     let (owner, _) = chan.socks();
-    owner.bind()?;
 
     let (mut fr, mut _cr) = owner.umem().rings();
     let (mut rx, mut _tx) = owner.rings();
 
-    let mut program = Program::from_file(&args.filepath, "pass_to_socket")?;
-    program.attach(ifindex)?;
-    program.update_map("xsks_map", 0, owner.fd())?;
-
-    for i in 0..fr.capacity() {
-        fr.enqueue(i as u64);
-    }
-
     println!("Polling");
 
     loop {
@@ -71,45 +79,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         println!("Got {} packets", rx.len());
 
+        let mut packets = 0;
         while let Some(desc) = rx.dequeue() {
             println!("Got packet: len={}", desc.len);
             fr.enqueue(desc.addr);
+            packets += 1;
         }
-    }
-}
 
-pub struct Program {
-    obj: bpf::Object,
-    prog: bpf::Program,
-}
-
-impl Program {
-    pub fn from_file(file: &str, name: &str) -> Result<Self, Box<dyn Error>> {
-        let buf = std::fs::read(file)?;
-        let obj = bpf::Object::create(&buf)?;
-        obj.load()?;
-        let prog = obj.find_program(name)?;
-        Ok(Program { obj, prog })
-    }
-
-    pub fn attach(&mut self, ifindex: u32) -> Result<(), Box<dyn Error>> {
-        self.prog.attach_xdp(ifindex)?;
-        Ok(())
-    }
-
-    pub fn update_map<V: IntoMapValue>(&mut self, name: &str, key: V, value: V) -> Result<(), Box<dyn Error>> {
-        let map = self.obj.find_map(name)?;
-        map.update(&key.into_value(), &value.into_value())?;
-        Ok(())
+        profiler.record(packets);
+        if profiler.elapsed() >= Duration::from_secs(1) {
+            println!("{}", profiler.sample()?);
+        }
     }
 }
-
-pub trait IntoMapValue {
-    fn into_value(self) -> Vec<u8>;
-}
-
-impl IntoMapValue for u32 {
-    fn into_value(self) -> Vec<u8> {
-        u32::to_le_bytes(self).to_vec()
-    }
-}
\ No newline at end of file